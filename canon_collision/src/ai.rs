@@ -1,31 +1,940 @@
+use crate::entity::components::body::Location;
+use crate::entity::{Entities, Entity, EntityKey};
 use crate::game::Game;
+
+use canon_collision_lib::entity_def::player::PlayerAction;
+use canon_collision_lib::entity_def::{CollisionBoxRole, EntityDef};
 use canon_collision_lib::input::state::ControllerInput;
+use canon_collision_lib::stage::{Stage, Surface};
+
+use rand::Rng;
+use rand_chacha::rand_core::SeedableRng;
+use rand_chacha::ChaChaRng;
+use treeflection::{KeyedContextVec, Node, NodeRunner, NodeToken};
+
+/// How many frames ahead of an opponents current frame are checked for hitboxes when deciding to shield
+const SHIELD_LOOKAHEAD_FRAMES: i64 = 3;
+
+/// Extra distance added to hitboxes, so the AI shields slightly before the hitbox arrives
+const SHIELD_MARGIN: f32 = 4.0;
+
+/// How close to the edges of the stage the AI is willing to walk
+const LEDGE_MARGIN: f32 = 10.0;
+
+/// How close to a blast zone the AI is willing to go before moving away from it
+const BLAST_MARGIN: f32 = 40.0;
+
+/// Attacks are only considered when the target is this close, to save checking every hitbox of every attack
+const ATTACK_RANGE: f32 = 60.0;
+
+/// How close to the floor a falling training dummy has to be before it presses tech
+const TECH_HEIGHT: f32 = 10.0;
+
+pub enum Behaviour {
+    /// Never presses anything
+    Idle,
+    /// Approaches, attacks, shields and recovers
+    Full,
+    /// Only DIs and recovers back to the stage
+    RecoverOnly,
+    /// Holds shield whenever it is on the ground
+    ShieldOnly,
+    /// Presses random inputs
+    Random,
+    /// Follows the options in Game::training_dummy
+    TrainingDummy,
+}
+
+pub struct AiProfile {
+    pub name: &'static str,
+    pub behaviour: Behaviour,
+    /// The AI sees its opponents as they were this many frames ago
+    pub reaction_delay: usize,
+    /// The chance of the AI taking each opportunity to attack or shield, from 0.0 to 1.0
+    pub accuracy: f32,
+}
+
+const fn level(name: &'static str, reaction_delay: usize, accuracy: f32) -> AiProfile {
+    AiProfile {
+        name,
+        behaviour: Behaviour::Full,
+        reaction_delay,
+        accuracy,
+    }
+}
+
+/// Every AI a CPU player can use, Game::selected_ais stores indexes into this
+pub const AI_PROFILES: [AiProfile; 14] = [
+    AiProfile {
+        name: "Idle",
+        behaviour: Behaviour::Idle,
+        reaction_delay: 0,
+        accuracy: 0.0,
+    },
+    level("Level 1", 24, 0.05),
+    level("Level 2", 21, 0.1),
+    level("Level 3", 18, 0.2),
+    level("Level 4", 15, 0.3),
+    level("Level 5", 12, 0.45),
+    level("Level 6", 9, 0.6),
+    level("Level 7", 6, 0.75),
+    level("Level 8", 3, 0.9),
+    level("Level 9", 0, 1.0),
+    AiProfile {
+        name: "Recover Only",
+        behaviour: Behaviour::RecoverOnly,
+        reaction_delay: 0,
+        accuracy: 1.0,
+    },
+    AiProfile {
+        name: "Shield Only",
+        behaviour: Behaviour::ShieldOnly,
+        reaction_delay: 0,
+        accuracy: 1.0,
+    },
+    AiProfile {
+        name: "Random",
+        behaviour: Behaviour::Random,
+        reaction_delay: 0,
+        accuracy: 1.0,
+    },
+    AiProfile {
+        name: "Training Dummy",
+        behaviour: Behaviour::TrainingDummy,
+        reaction_delay: 0,
+        accuracy: 1.0,
+    },
+];
+
+/// The profile used by CPU players that have not chosen one: Level 5
+pub const DEFAULT_AI_PROFILE: usize = 5;
 
+/// Options for CPU players using the Training Dummy profile, shared by every dummy in the game
+#[derive(Clone, Default, Serialize, Deserialize, Node)]
+pub struct TrainingDummy {
+    /// Direction held while in hitstun
+    pub di: DummyDi,
+    /// How to react when landing while tumbling
+    pub tech: DummyTech,
+    /// Hold shield whenever on the ground and not in hitstun
+    pub hold_shield: bool,
+    /// Jump as soon as hitstun ends
+    pub jump_out_of_hitstun: bool,
+    /// Performed whenever the dummy can act, unless it is holding shield
+    pub action: DummyAction,
+}
+
+#[derive(Clone, Serialize, Deserialize, Node)]
+pub enum DummyDi {
+    None,
+    Up,
+    Down,
+    Left,
+    Right,
+    TowardsStage,
+    AwayFromStage,
+    Random,
+}
+
+impl Default for DummyDi {
+    fn default() -> Self {
+        DummyDi::None
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Node)]
+pub enum DummyTech {
+    /// Always miss the tech
+    Never,
+    Neutral,
+    Forward,
+    Backward,
+    /// Randomly pick one of the above for each knockdown
+    Random,
+}
+
+impl Default for DummyTech {
+    fn default() -> Self {
+        DummyTech::Never
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Node)]
+pub enum DummyAction {
+    None,
+    Jump,
+    Jab,
+    Ftilt,
+    Utilt,
+    Dtilt,
+    Fsmash,
+    Usmash,
+    Dsmash,
+    Nair,
+    Fair,
+    Bair,
+    Uair,
+    Dair,
+    Nspecial,
+    Sspecial,
+    Uspecial,
+    Dspecial,
+    Grab,
+}
+
+impl Default for DummyAction {
+    fn default() -> Self {
+        DummyAction::None
+    }
+}
+
+/// Generates the inputs of every CPU player for the next frame
 pub fn gen_inputs(game: &Game) -> Vec<ControllerInput> {
+    let entities = game.entities();
+
+    // CPU players are always added after the human players, so the last players are controlled by the AIs
+    let first_ai_player = game
+        .selected_controllers
+        .len()
+        .saturating_sub(game.selected_ais.len());
+
     game.selected_ais
         .iter()
-        .map(|_| ControllerInput {
-            plugged_in: true,
-
-            up: false,
-            down: false,
-            right: false,
-            left: false,
-            y: false,
-            x: false,
-            b: false,
-            a: false,
-            l: false,
-            r: false,
-            z: false,
-            start: false,
-
-            stick_x: 0.0,
-            stick_y: 0.0,
-            c_stick_x: 0.0,
-            c_stick_y: 0.0,
-            l_trigger: 0.0,
-            r_trigger: 0.0,
+        .enumerate()
+        .map(|(i, profile)| {
+            let player_id = first_ai_player + i;
+            let profile = AI_PROFILES.get(*profile).unwrap_or(&AI_PROFILES[0]);
+            entities
+                .iter()
+                .find(|(_, entity)| entity.ty.get_player().map(|x| x.id) == Some(player_id))
+                .map(|(key, _)| gen_input(game, &entities, key, profile))
+                .unwrap_or_else(neutral_input)
         })
         .collect()
 }
+
+fn neutral_input() -> ControllerInput {
+    ControllerInput {
+        plugged_in: true,
+        ..ControllerInput::empty()
+    }
+}
+
+/// A player as seen by the AI
+struct Fighter<'a> {
+    key: EntityKey,
+    entity: &'a Entity,
+    x: f32,
+    y: f32,
+    action: Option<PlayerAction>,
+}
+
+impl<'a> Fighter<'a> {
+    fn new(game: &Game, entities: &'a Entities, key: EntityKey) -> Fighter<'a> {
+        let entity = &entities[key];
+        let (x, y) = entity.public_bps_xy(entities, &game.package.entities, &game.stage.surfaces);
+        Fighter {
+            key,
+            entity,
+            x,
+            y,
+            action: entity.state.get_action(),
+        }
+    }
+
+    fn airbourne(&self) -> bool {
+        match self.entity.ty.get_player() {
+            Some(player) => matches!(player.body.location, Location::Airbourne { .. }),
+            None => false,
+        }
+    }
+
+    fn alive(&self) -> bool {
+        !matches!(
+            self.action,
+            Some(PlayerAction::Eliminated)
+                | Some(PlayerAction::ReSpawn)
+                | Some(PlayerAction::ReSpawnIdle)
+        )
+    }
+
+    fn in_hitstun(&self) -> bool {
+        matches!(
+            self.action,
+            Some(PlayerAction::Damage)
+                | Some(PlayerAction::DamageFly)
+                | Some(PlayerAction::DamageFall)
+        )
+    }
+}
+
+/// The horizontal extent and height of the solid floors of the stage, platforms that can be passed through are ignored
+struct StageBounds {
+    left: f32,
+    right: f32,
+    top: f32,
+}
+
+impl StageBounds {
+    fn new(stage: &Stage) -> StageBounds {
+        let mut bounds: Option<StageBounds> = None;
+        for surface in stage.surfaces.iter() {
+            if surface.floor.is_some() && !surface.is_pass_through() {
+                let (left, left_y) = surface.left_ledge();
+                let (right, right_y) = surface.right_ledge();
+                let top = left_y.max(right_y);
+                bounds = Some(match bounds {
+                    Some(bounds) => StageBounds {
+                        left: bounds.left.min(left),
+                        right: bounds.right.max(right),
+                        top: bounds.top.max(top),
+                    },
+                    None => StageBounds { left, right, top },
+                });
+            }
+        }
+        bounds.unwrap_or(StageBounds {
+            left: 0.0,
+            right: 0.0,
+            top: 0.0,
+        })
+    }
+
+    fn center(&self) -> f32 {
+        (self.left + self.right) / 2.0
+    }
+
+    fn contains_x(&self, x: f32) -> bool {
+        self.left <= x && x <= self.right
+    }
+}
+
+/// Decides the inputs of a single CPU player, in priority order:
+/// *   escape hitstun and recover back to the stage
+/// *   shield when an opponents hitbox is about to reach it
+/// *   attack when one of its moves reaches the target
+/// *   approach the closest opponent
+fn gen_input(
+    game: &Game,
+    entities: &Entities,
+    key: EntityKey,
+    profile: &AiProfile,
+) -> ControllerInput {
+    let mut input = neutral_input();
+    let cpu = Fighter::new(game, entities, key);
+    let player = match cpu.entity.ty.get_player() {
+        Some(player) => player,
+        None => return input,
+    };
+    if !cpu.alive() {
+        return input;
+    }
+
+    // Seeded from the game so that every CPU makes the same decisions when a frame is resimulated
+    let mut rng = ChaChaRng::seed_from_u64(
+        game.init_seed ^ ((game.current_frame as u64) << 8) ^ player.id as u64,
+    );
+
+    match profile.behaviour {
+        Behaviour::Idle => return input,
+        Behaviour::Random => return random_input(&mut rng),
+        Behaviour::ShieldOnly => {
+            if !cpu.airbourne() {
+                input.r = true;
+                input.r_trigger = 1.0;
+            }
+            return input;
+        }
+        Behaviour::TrainingDummy => return training_dummy(game, &cpu, &mut rng),
+        Behaviour::Full | Behaviour::RecoverOnly => {}
+    }
+
+    let entity_defs = &game.package.entities;
+    let entity_def = &entity_defs[cpu.entity.state.entity_def_key.as_ref()];
+    let bounds = StageBounds::new(&game.stage);
+    let blast = &game.stage.blast;
+
+    // Buttons are only held on every second frame, so that every press is seen as a new press
+    let press = game.current_frame % 2 == 0;
+
+    // Opponents are seen as they were a few frames ago, to simulate a reaction time
+    let perceived = game.entities_frames_ago(profile.reaction_delay);
+    let target = perceived
+        .iter()
+        .filter(|(key, entity)| {
+            *key != cpu.key
+                && entity
+                    .ty
+                    .get_player()
+                    .map_or(false, |x| x.team != player.team)
+        })
+        .map(|(key, _)| Fighter::new(game, perceived, key))
+        .filter(|x| x.alive())
+        .min_by(|a, b| {
+            let a_distance = (a.x - cpu.x).powi(2) + (a.y - cpu.y).powi(2);
+            let b_distance = (b.x - cpu.x).powi(2) + (b.y - cpu.y).powi(2);
+            a_distance.total_cmp(&b_distance)
+        });
+
+    // Survival DI: launch towards the center of the stage and away from the blast zones
+    if cpu.in_hitstun() {
+        input.stick_x = (bounds.center() - cpu.x).signum();
+        input.stick_y = if cpu.y > blast.top() - BLAST_MARGIN {
+            -1.0
+        } else {
+            1.0
+        };
+        return input;
+    }
+
+    // get up from the ledge
+    if let Some(PlayerAction::LedgeIdle) | Some(PlayerAction::LedgeIdleChain) = cpu.action {
+        if press {
+            input.stick_x = cpu.entity.relative_f(1.0);
+        }
+        return input;
+    }
+
+    if cpu.airbourne() && !bounds.contains_x(cpu.x) {
+        recover(
+            &mut input,
+            &cpu,
+            player.air_jumps_left,
+            &game.stage,
+            &bounds,
+            press,
+        );
+        return input;
+    }
+
+    if let Behaviour::RecoverOnly = profile.behaviour {
+        return input;
+    }
+
+    if cpu.y > blast.top() - BLAST_MARGIN {
+        // fastfall away from the top blast zone
+        input.stick_y = if press { -1.0 } else { 0.0 };
+        return input;
+    }
+
+    let actionable = cpu.entity.state.interruptible(entity_def);
+    if !actionable {
+        return input;
+    }
+
+    let target = match target {
+        Some(target) => target,
+        None => return input,
+    };
+
+    if !cpu.airbourne() && rng.gen::<f32>() < profile.accuracy && threatened(game, perceived, &cpu)
+    {
+        input.r = true;
+        input.r_trigger = 1.0;
+        return input;
+    }
+
+    if press && rng.gen::<f32>() < profile.accuracy && attack(&mut input, game, &cpu, &target) {
+        return input;
+    }
+
+    approach(&mut input, &cpu, &target, &game.stage, &bounds, press);
+    input
+}
+
+fn random_input(rng: &mut ChaChaRng) -> ControllerInput {
+    ControllerInput {
+        plugged_in: true,
+
+        up: false,
+        down: false,
+        right: false,
+        left: false,
+        y: rng.gen_bool(0.05),
+        x: rng.gen_bool(0.05),
+        b: rng.gen_bool(0.05),
+        a: rng.gen_bool(0.1),
+        l: false,
+        r: rng.gen_bool(0.05),
+        z: rng.gen_bool(0.02),
+        start: false,
+
+        stick_x: rng.gen_range(-1.0..=1.0),
+        stick_y: rng.gen_range(-1.0..=1.0),
+        c_stick_x: 0.0,
+        c_stick_y: 0.0,
+        l_trigger: 0.0,
+        r_trigger: 0.0,
+    }
+}
+
+/// Follows the options in Game::training_dummy, recovering back to the stage when knocked off it
+fn training_dummy(game: &Game, cpu: &Fighter, rng: &mut ChaChaRng) -> ControllerInput {
+    let mut input = neutral_input();
+    let player = match cpu.entity.ty.get_player() {
+        Some(player) => player,
+        None => return input,
+    };
+    let options = &game.training_dummy;
+    let bounds = StageBounds::new(&game.stage);
+    let press = game.current_frame % 2 == 0;
+
+    // Seeded from the frame of the last hit so that random choices stay the same for the whole knockdown
+    let hit_frame = (game.current_frame as u64).saturating_sub(player.body.frames_since_hit);
+    let mut knockdown_rng =
+        ChaChaRng::seed_from_u64(game.init_seed ^ (hit_frame << 8) ^ player.id as u64);
+
+    match cpu.action {
+        Some(PlayerAction::Damage) | Some(PlayerAction::DamageFly) => {
+            let (stick_x, stick_y) = dummy_di(&options.di, cpu, &bounds, &mut knockdown_rng);
+            input.stick_x = stick_x;
+            input.stick_y = stick_y;
+            dummy_tech(
+                &mut input,
+                &options.tech,
+                game,
+                cpu,
+                &mut knockdown_rng,
+                press,
+            );
+            return input;
+        }
+        Some(PlayerAction::DamageFall) => {
+            if options.jump_out_of_hitstun && press && player.air_jumps_left > 0 {
+                input.x = true;
+            } else {
+                dummy_tech(
+                    &mut input,
+                    &options.tech,
+                    game,
+                    cpu,
+                    &mut knockdown_rng,
+                    press,
+                );
+            }
+            return input;
+        }
+        Some(PlayerAction::LedgeIdle) | Some(PlayerAction::LedgeIdleChain) => {
+            if press {
+                input.stick_x = cpu.entity.relative_f(1.0);
+            }
+            return input;
+        }
+        _ => {}
+    }
+
+    if cpu.airbourne() && !bounds.contains_x(cpu.x) {
+        recover(
+            &mut input,
+            cpu,
+            player.air_jumps_left,
+            &game.stage,
+            &bounds,
+            press,
+        );
+        return input;
+    }
+
+    if options.hold_shield && !cpu.airbourne() {
+        input.r = true;
+        input.r_trigger = 1.0;
+        return input;
+    }
+
+    let entity_def = &game.package.entities[cpu.entity.state.entity_def_key.as_ref()];
+    if press && cpu.entity.state.interruptible(entity_def) {
+        dummy_action(&mut input, &options.action, cpu, rng);
+    }
+    input
+}
+
+fn dummy_di(di: &DummyDi, cpu: &Fighter, bounds: &StageBounds, rng: &mut ChaChaRng) -> (f32, f32) {
+    let towards_stage = (bounds.center() - cpu.x).signum();
+    match di {
+        DummyDi::None => (0.0, 0.0),
+        DummyDi::Up => (0.0, 1.0),
+        DummyDi::Down => (0.0, -1.0),
+        DummyDi::Left => (-1.0, 0.0),
+        DummyDi::Right => (1.0, 0.0),
+        DummyDi::TowardsStage => (towards_stage, 0.0),
+        DummyDi::AwayFromStage => (-towards_stage, 0.0),
+        DummyDi::Random => {
+            let angle = rng.gen_range(0.0..std::f32::consts::PI * 2.0);
+            (angle.cos(), angle.sin())
+        }
+    }
+}
+
+/// Presses shield just before landing while tumbling, holding the stick in the direction to tech
+fn dummy_tech(
+    input: &mut ControllerInput,
+    tech: &DummyTech,
+    game: &Game,
+    cpu: &Fighter,
+    rng: &mut ChaChaRng,
+    press: bool,
+) {
+    let direction = match tech {
+        DummyTech::Never => return,
+        DummyTech::Neutral => 0.0,
+        DummyTech::Forward => 1.0,
+        DummyTech::Backward => -1.0,
+        DummyTech::Random => match rng.gen_range(0..4) {
+            0 => return,
+            1 => 0.0,
+            2 => 1.0,
+            _ => -1.0,
+        },
+    };
+
+    let falling = cpu
+        .entity
+        .ty
+        .get_player()
+        .map_or(false, |x| x.body.y_vel < 0.0);
+    let near_floor = height_above_floor(&game.stage.surfaces, cpu.x, cpu.y)
+        .map_or(false, |height| height < TECH_HEIGHT);
+    if falling && near_floor {
+        input.stick_x = cpu.entity.relative_f(direction);
+        input.stick_y = 0.0;
+        input.l = press;
+    }
+}
+
+/// Presses the inputs for the chosen action, aerials are preceded by a jump when on the ground
+fn dummy_action(
+    input: &mut ControllerInput,
+    action: &DummyAction,
+    cpu: &Fighter,
+    rng: &mut ChaChaRng,
+) {
+    let aerial = matches!(
+        action,
+        DummyAction::Nair
+            | DummyAction::Fair
+            | DummyAction::Bair
+            | DummyAction::Uair
+            | DummyAction::Dair
+    );
+    if aerial && !cpu.airbourne() {
+        input.x = true;
+        return;
+    }
+
+    // (a, b, z, stick_x, stick_y, c_stick_y) with stick_x relative to the direction the cpu is facing
+    let (a, b, z, stick_x, stick_y, c_stick_y) = match action {
+        DummyAction::None => return,
+        DummyAction::Jump => {
+            input.x = true;
+            return;
+        }
+        DummyAction::Jab => (true, false, false, 0.0, 0.0, 0.0),
+        DummyAction::Ftilt => (true, false, false, 0.5, 0.0, 0.0),
+        DummyAction::Utilt => (true, false, false, 0.0, 0.5, 0.0),
+        DummyAction::Dtilt => (true, false, false, 0.0, -0.5, 0.0),
+        DummyAction::Usmash => (false, false, false, 0.0, 0.0, 1.0),
+        DummyAction::Dsmash => (false, false, false, 0.0, 0.0, -1.0),
+        DummyAction::Fsmash => {
+            input.c_stick_x = cpu.entity.relative_f(1.0);
+            return;
+        }
+        DummyAction::Nair => (true, false, false, 0.0, 0.0, 0.0),
+        DummyAction::Fair => (true, false, false, 1.0, 0.0, 0.0),
+        DummyAction::Bair => (true, false, false, -1.0, 0.0, 0.0),
+        DummyAction::Uair => (true, false, false, 0.0, 1.0, 0.0),
+        DummyAction::Dair => (true, false, false, 0.0, -1.0, 0.0),
+        DummyAction::Nspecial => (false, true, false, 0.0, 0.0, 0.0),
+        DummyAction::Sspecial => {
+            // pick a random side so the dummy stays in roughly the same place
+            let stick_x = if rng.gen() { 1.0 } else { -1.0 };
+            (false, true, false, stick_x, 0.0, 0.0)
+        }
+        DummyAction::Uspecial => (false, true, false, 0.0, 1.0, 0.0),
+        DummyAction::Dspecial => (false, true, false, 0.0, -1.0, 0.0),
+        DummyAction::Grab => (false, false, true, 0.0, 0.0, 0.0),
+    };
+    input.a = a;
+    input.b = b;
+    input.z = z;
+    input.stick_x = cpu.entity.relative_f(stick_x);
+    input.stick_y = stick_y;
+    input.c_stick_y = c_stick_y;
+}
+
+/// Distance from the point down to the closest floor beneath it
+fn height_above_floor(surfaces: &[Surface], x: f32, y: f32) -> Option<f32> {
+    surfaces
+        .iter()
+        .filter(|surface| surface.floor.is_some() && surface.world_x_in_bounds(x))
+        .map(|surface| y - surface.world_x_to_world_y(x))
+        .filter(|height| *height >= 0.0)
+        .min_by(|a, b| a.total_cmp(b))
+}
+
+/// Drift back towards the closest ledge, jumping and then using up special when falling below it
+fn recover(
+    input: &mut ControllerInput,
+    cpu: &Fighter,
+    air_jumps_left: u64,
+    stage: &Stage,
+    bounds: &StageBounds,
+    press: bool,
+) {
+    let (ledge_x, ledge_y) =
+        closest_ledge(&stage.surfaces, cpu.x, cpu.y).unwrap_or((bounds.center(), bounds.top));
+    input.stick_x = (ledge_x - cpu.x).signum();
+
+    if cpu.y < ledge_y && press {
+        match cpu.action {
+            Some(PlayerAction::SpecialFall)
+            | Some(PlayerAction::UspecialAirStart)
+            | Some(PlayerAction::JumpAerialF)
+            | Some(PlayerAction::JumpAerialB) => {}
+            _ if air_jumps_left > 0 => input.x = true,
+            _ => {
+                input.stick_x = 0.0;
+                input.stick_y = 1.0;
+                input.b = true;
+            }
+        }
+    }
+}
+
+fn closest_ledge(surfaces: &[Surface], x: f32, y: f32) -> Option<(f32, f32)> {
+    let mut ledges = vec![];
+    for surface in surfaces {
+        if surface.left_grab() {
+            ledges.push(surface.left_ledge());
+        }
+        if surface.right_grab() {
+            ledges.push(surface.right_ledge());
+        }
+    }
+    ledges.into_iter().min_by(|a, b| {
+        let a_distance = (a.0 - x).powi(2) + (a.1 - y).powi(2);
+        let b_distance = (b.0 - x).powi(2) + (b.1 - y).powi(2);
+        a_distance.total_cmp(&b_distance)
+    })
+}
+
+/// Returns true if an opponents hitbox will overlap one of the cpu's hurtboxes within the next few frames
+fn threatened(game: &Game, entities: &Entities, cpu: &Fighter) -> bool {
+    let entity_defs = &game.package.entities;
+    let hurtboxes = hurtboxes(cpu, entity_defs, &game.stage.surfaces);
+    let cpu_player_id = cpu.entity.player_id();
+
+    for (key, entity) in entities.iter() {
+        if key == cpu.key || entity.player_id() == cpu_player_id {
+            continue;
+        }
+        let (x, y) = entity.public_bps_xy(entities, entity_defs, &game.stage.surfaces);
+        let entity_def = &entity_defs[entity.state.entity_def_key.as_ref()];
+        let action = &entity.state.action;
+        for frame in entity.state.frame..=entity.state.frame + SHIELD_LOOKAHEAD_FRAMES {
+            for (hit_x, hit_y, hit_radius) in hitboxes(entity, entity_def, action, frame) {
+                for &(hurt_x, hurt_y, hurt_radius) in &hurtboxes {
+                    let distance =
+                        ((x + hit_x - hurt_x).powi(2) + (y + hit_y - hurt_y).powi(2)).sqrt();
+                    if distance < hit_radius + hurt_radius + SHIELD_MARGIN {
+                        return true;
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Presses the inputs for the first attack that will hit the target if it stays still.
+/// Returns true if an attack was chosen.
+fn attack(input: &mut ControllerInput, game: &Game, cpu: &Fighter, target: &Fighter) -> bool {
+    if (target.x - cpu.x).abs() > ATTACK_RANGE || (target.y - cpu.y).abs() > ATTACK_RANGE {
+        return false;
+    }
+
+    let entity_defs = &game.package.entities;
+    let entity_def = &entity_defs[cpu.entity.state.entity_def_key.as_ref()];
+    let hurtboxes = hurtboxes(target, entity_defs, &game.stage.surfaces);
+    let facing_target = (target.x > cpu.x) == cpu.entity.face_right();
+
+    // (action, stick_x, stick_y, c_stick_x, c_stick_y) relative to the direction the cpu is facing
+    let attacks: &[(PlayerAction, f32, f32, f32, f32)] = if cpu.airbourne() {
+        &[
+            (PlayerAction::Nair, 0.0, 0.0, 0.0, 0.0),
+            (PlayerAction::Fair, 1.0, 0.0, 0.0, 0.0),
+            (PlayerAction::Bair, -1.0, 0.0, 0.0, 0.0),
+            (PlayerAction::Uair, 0.0, 1.0, 0.0, 0.0),
+            (PlayerAction::Dair, 0.0, -1.0, 0.0, 0.0),
+        ]
+    } else {
+        &[
+            (PlayerAction::Jab, 0.0, 0.0, 0.0, 0.0),
+            (PlayerAction::Ftilt, 0.5, 0.0, 0.0, 0.0),
+            (PlayerAction::Utilt, 0.0, 0.5, 0.0, 0.0),
+            (PlayerAction::Dtilt, 0.0, -0.5, 0.0, 0.0),
+            (PlayerAction::Fsmash, 0.0, 0.0, 1.0, 0.0),
+            (PlayerAction::Usmash, 0.0, 0.0, 0.0, 1.0),
+            (PlayerAction::Dsmash, 0.0, 0.0, 0.0, -1.0),
+        ]
+    };
+
+    for (action, stick_x, stick_y, c_stick_x, c_stick_y) in attacks.iter() {
+        // ground attacks with a forwards input can only hit in front of the cpu, except for smashes which turn the cpu around
+        let needs_facing = *stick_x > 0.0;
+        if needs_facing && !facing_target {
+            continue;
+        }
+        let turn_around = *c_stick_x > 0.0 && !facing_target;
+        let face_right = cpu.entity.face_right() != turn_around;
+        let direction = if face_right { 1.0 } else { -1.0 };
+
+        let action_name: &str = action.clone().into();
+        if !entity_def.actions.contains_key(&action_name.to_string()) {
+            continue;
+        }
+        let frames = entity_def.actions[action_name].frames.len() as i64;
+        let reaches = (0..frames).any(|frame| {
+            hitboxes_facing(entity_def, action_name, frame, face_right)
+                .iter()
+                .any(|&(hit_x, hit_y, hit_radius)| {
+                    hurtboxes.iter().any(|&(hurt_x, hurt_y, hurt_radius)| {
+                        let distance = ((cpu.x + hit_x - hurt_x).powi(2)
+                            + (cpu.y + hit_y - hurt_y).powi(2))
+                        .sqrt();
+                        distance < hit_radius + hurt_radius
+                    })
+                })
+        });
+
+        if reaches {
+            input.a = *c_stick_x == 0.0 && *c_stick_y == 0.0;
+            input.stick_x = stick_x * direction;
+            input.stick_y = *stick_y;
+            input.c_stick_x = c_stick_x * direction;
+            input.c_stick_y = *c_stick_y;
+            return true;
+        }
+    }
+    false
+}
+
+/// Move towards the target without walking off the stage, jumping or dropping through platforms to reach it
+fn approach(
+    input: &mut ControllerInput,
+    cpu: &Fighter,
+    target: &Fighter,
+    stage: &Stage,
+    bounds: &StageBounds,
+    press: bool,
+) {
+    let target_x = if bounds.left + LEDGE_MARGIN < bounds.right - LEDGE_MARGIN {
+        target
+            .x
+            .max(bounds.left + LEDGE_MARGIN)
+            .min(bounds.right - LEDGE_MARGIN)
+    } else {
+        bounds.center()
+    };
+    let d_x = target_x - cpu.x;
+    if d_x.abs() > 5.0 {
+        input.stick_x = d_x.signum();
+    }
+
+    if cpu.airbourne() {
+        return;
+    }
+
+    let d_y = target.y - cpu.y;
+    if d_y > 20.0 && (target.x - cpu.x).abs() < 30.0 && press {
+        input.x = true;
+    } else if d_y < -15.0 && on_pass_through_platform(cpu, &stage.surfaces) {
+        input.stick_x = 0.0;
+        input.stick_y = if press { -1.0 } else { 0.0 };
+    }
+}
+
+fn on_pass_through_platform(cpu: &Fighter, surfaces: &[Surface]) -> bool {
+    match cpu.entity.ty.get_player().map(|x| &x.body.location) {
+        Some(Location::Surface { platform_i, .. }) => surfaces
+            .get(*platform_i)
+            .map_or(false, |x| x.is_pass_through()),
+        _ => false,
+    }
+}
+
+/// The world position and radius of every hurtbox of the fighter on its current frame
+fn hurtboxes(
+    fighter: &Fighter,
+    entity_defs: &KeyedContextVec<EntityDef>,
+    surfaces: &[Surface],
+) -> Vec<(f32, f32, f32)> {
+    let entity_def = &entity_defs[fighter.entity.state.entity_def_key.as_ref()];
+    let frame = fighter.entity.relative_frame(entity_def, surfaces);
+    let hurtboxes: Vec<_> = frame
+        .colboxes
+        .iter()
+        .filter(|x| matches!(x.role, CollisionBoxRole::Hurt(_)))
+        .map(|x| (fighter.x + x.point.0, fighter.y + x.point.1, x.radius))
+        .collect();
+
+    if hurtboxes.is_empty() {
+        // Fall back to a single hurtbox around the ECB
+        let ecb = &frame.ecb;
+        let radius = (ecb.top - ecb.bottom).abs() / 2.0;
+        vec![(fighter.x, fighter.y + (ecb.top + ecb.bottom) / 2.0, radius)]
+    } else {
+        hurtboxes
+    }
+}
+
+/// The position relative to the entity and radius of every hitbox on the specified frame of the entities action
+fn hitboxes(
+    entity: &Entity,
+    entity_def: &EntityDef,
+    action: &str,
+    frame: i64,
+) -> Vec<(f32, f32, f32)> {
+    hitboxes_facing(entity_def, action, frame, entity.face_right())
+}
+
+fn hitboxes_facing(
+    entity_def: &EntityDef,
+    action: &str,
+    frame: i64,
+    face_right: bool,
+) -> Vec<(f32, f32, f32)> {
+    if frame < 0 || !entity_def.actions.contains_key(&action.to_string()) {
+        return vec![];
+    }
+    let frames = &entity_def.actions[action].frames;
+    match frames.get(frame as usize) {
+        Some(frame) => frame
+            .colboxes
+            .iter()
+            .filter(|x| matches!(x.role, CollisionBoxRole::Hit(_)))
+            .map(|x| {
+                let hit_x = if face_right { x.point.0 } else { -x.point.0 };
+                (hit_x, x.point.1, x.radius)
+            })
+            .collect(),
+        None => vec![],
+    }
+}
+
+#[test]
+fn stage_bounds_ignore_pass_through_platforms() {
+    let bounds = StageBounds::new(&Stage::default());
+    assert_eq!(bounds.left, -75.0);
+    assert_eq!(bounds.right, 75.0);
+    assert_eq!(bounds.top, 0.0);
+    assert!(bounds.contains_x(0.0));
+    assert!(!bounds.contains_x(80.0));
+}
+
+#[test]
+fn height_above_floor_uses_closest_floor_below() {
+    let stage = Stage::default();
+    assert_eq!(height_above_floor(&stage.surfaces, 50.0, 60.0), Some(10.0));
+    assert_eq!(height_above_floor(&stage.surfaces, 50.0, 40.0), Some(40.0));
+    assert_eq!(height_above_floor(&stage.surfaces, 100.0, 40.0), None);
+}
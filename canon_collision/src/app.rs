@@ -7,6 +7,7 @@ use crate::camera::Camera;
 use crate::cli::{CLIResults, ContinueFrom};
 use crate::game::{Edit, Game, GameSetup, GameState, PlayerSetup};
 use crate::graphics::GraphicsMessage;
+use crate::hot_reload;
 use crate::menu::{Menu, MenuState, ResumeMenu};
 use crate::replays;
 use crate::rules::Rules;
@@ -14,12 +15,12 @@ use canon_collision_lib::assets::Assets;
 use canon_collision_lib::command_line::CommandLine;
 use canon_collision_lib::config::Config;
 use canon_collision_lib::input::Input;
-use canon_collision_lib::network::{NetCommandLine, Netplay, NetplayState};
+use canon_collision_lib::network::{NetCommandLine, Netplay, NetplayState, UdpTransport};
 use canon_collision_lib::package::Package;
 
 use std::sync::mpsc::channel;
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 use winit::event::WindowEvent;
 use winit_input_helper::WinitInputHelper;
 
@@ -46,7 +47,9 @@ fn run(
 
     let mut input = Input::new();
     let mut net_command_line = NetCommandLine::new();
-    let mut netplay = Netplay::new();
+    let netplay_port = cli_results.netplay_port.unwrap_or(config.netplay_port);
+    let mut netplay = Netplay::new(Box::new(UdpTransport::new(netplay_port)));
+    netplay.set_config(&config);
 
     let mut package = if let Some(path) = Package::find_package_in_parent_dirs() {
         if let Some(package) = Package::open(path) {
@@ -143,7 +146,7 @@ fn run(
                             team: players_len + i,
                         });
                         controllers.push(input_len + i);
-                        ais.push(0);
+                        ais.push(ai::DEFAULT_AI_PROFILE);
                     }
                 }
 
@@ -173,6 +176,7 @@ fn run(
                     edit: Edit::Stage,
                     hot_reload_entities: None,
                     hot_reload_stage: None,
+                    events: vec![],
                     rules,
                     controllers,
                     players,
@@ -183,9 +187,32 @@ fn run(
                     Some(Game::new(package.take().unwrap(), setup, &mut audio)),
                 )
             }
-            ContinueFrom::ReplayFile(file_name) => match replays::load_replay(&file_name) {
-                Ok(replay) => {
-                    let mut game_setup = replay.into_game_setup(true);
+            ContinueFrom::ReplayFile(name) => {
+                match replays::load_replay(&name).and_then(|replay| {
+                    replay
+                        .check_compatible(package.as_ref().unwrap())
+                        .map(|_| replay)
+                }) {
+                    Ok(replay) => {
+                        let mut game_setup = replay.into_game_setup();
+                        input.set_history(std::mem::take(&mut game_setup.input_history));
+                        (
+                            Menu::new(MenuState::character_select()),
+                            Some(Game::new(package.take().unwrap(), game_setup, &mut audio)),
+                        )
+                    }
+                    Err(err) => {
+                        println!(
+                            "Failed to load replay with name '{}', because: {}",
+                            name, err
+                        );
+                        return;
+                    }
+                }
+            }
+            ContinueFrom::HotReload => match hot_reload::load_snapshot() {
+                Ok(snapshot) => {
+                    let mut game_setup = snapshot.into_game_setup();
                     input.set_history(std::mem::take(&mut game_setup.input_history));
                     (
                         Menu::new(MenuState::character_select()),
@@ -193,16 +220,22 @@ fn run(
                     )
                 }
                 Err(err) => {
-                    println!(
-                        "Failed to load replay with filename '{}', because: {}",
-                        file_name, err
-                    );
+                    println!("Failed to load hot reload snapshot, because: {}", err);
                     return;
                 }
             },
             ContinueFrom::Netplay => {
                 audio.play_bgm("Menu");
-                netplay.direct_connect(cli_results.address.unwrap());
+                netplay.direct_connect(&cli_results.addresses);
+                let state = MenuState::NetplayWait {
+                    message: String::from(""),
+                };
+
+                (Menu::new(state), None)
+            }
+            ContinueFrom::Spectate(address) => {
+                audio.play_bgm("Menu");
+                netplay.spectate(address, config.netplay_spectator_delay);
                 let state = MenuState::NetplayWait {
                     message: String::from(""),
                 };
@@ -212,6 +245,7 @@ fn run(
             ContinueFrom::MatchMaking => {
                 audio.play_bgm("Menu");
                 netplay.connect_match_making(
+                    config.netplay_matchmaking_host.clone(),
                     cli_results
                         .netplay_region
                         .unwrap_or(config.netplay_region.clone().unwrap_or_else(|| "AU".into())),
@@ -223,7 +257,12 @@ fn run(
 
                 (Menu::new(state), None)
             }
-            ContinueFrom::Close => unreachable!(),
+            ContinueFrom::Close
+            | ContinueFrom::Simulate(_)
+            | ContinueFrom::Verify(_)
+            | ContinueFrom::ExportFrames(_) => {
+                unreachable!()
+            }
         }
     };
 
@@ -263,7 +302,7 @@ fn run(
                     &mut input,
                     &os_input,
                     command_line.block(),
-                    &netplay,
+                    &mut netplay,
                     &mut audio,
                 ) {
                     resume_menu = Some(resume_menu_inner)
@@ -286,6 +325,8 @@ fn run(
                 &mut netplay,
             ) {
                 input.set_history(std::mem::take(&mut menu_game_setup.input_history));
+                // The game counts its frames from 0, but netplay inputs are indexed from the start of the session
+                netplay.reset_frame_origin();
                 game = Some(Game::new(
                     package.take().unwrap(),
                     menu_game_setup,
@@ -306,6 +347,7 @@ fn run(
             input.reset_history();
             game = None;
             menu.resume(resume_menu, &mut audio);
+            netplay.reset_frame_origin();
 
             // Game -> Menu Transitions
             // Game complete   -> display results -> CSS
@@ -319,7 +361,7 @@ fn run(
             return;
         }
 
-        let frame_duration = Duration::from_secs(1) / 60;
+        let frame_duration = netplay.frame_duration();
         let frame_elapsed = frame_start.elapsed();
         if frame_elapsed < frame_duration {
             spin_sleep::sleep(frame_duration - frame_elapsed);
@@ -18,10 +18,12 @@ pub mod sfx;
 use sfx::{Sfx, SfxType};
 
 pub struct Audio {
-    manager: AudioManager,
+    /// None when muted
+    manager: Option<AudioManager>,
     path: PathBuf,
     bgm: Option<InstanceHandle>,
-    sfx: Sfx,
+    /// None when muted
+    sfx: Option<Sfx>,
 }
 
 impl Audio {
@@ -31,15 +33,27 @@ impl Audio {
         let sfx = Sfx::new(&mut manager, &path);
 
         Audio {
-            manager,
+            manager: Some(manager),
             path,
-            sfx,
+            sfx: Some(sfx),
+            bgm: None,
+        }
+    }
+
+    /// Create an Audio that never plays anything, so that no audio device or assets are required.
+    pub fn muted() -> Self {
+        Audio {
+            manager: None,
+            path: PathBuf::new(),
+            sfx: None,
             bgm: None,
         }
     }
 
     pub fn play_sound_effect(&mut self, entity: &EntityDef, sfx: SfxType) {
-        self.sfx.play_sound_effect(entity, sfx);
+        if let Some(sfx_player) = &mut self.sfx {
+            sfx_player.play_sound_effect(entity, sfx);
+        }
     }
 
     /// Folders can contain music organized by stage/menu or fighter
@@ -55,6 +69,7 @@ impl Audio {
     }
 
     fn play_bgm_inner(&mut self, folder: &str) -> Result<BGMMetadata, String> {
+        let manager = self.manager.as_mut().ok_or("Audio is muted")?;
         let folder = folder.replace(' ', "");
         let read_dir =
             fs::read_dir(self.path.join("music").join(&folder)).map_err(|x| x.to_string())?;
@@ -73,8 +88,7 @@ impl Audio {
             .ok_or("No files in folder")?;
 
         let basic_loop = SoundSettings::default().default_loop_start(0.0);
-        let mut new_sound = self
-            .manager
+        let mut new_sound = manager
             .load_sound(chosen_file.path(), basic_loop)
             .map_err(|x| x.to_string())?;
 
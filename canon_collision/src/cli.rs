@@ -1,6 +1,7 @@
+use canon_collision_lib::network::DEFAULT_PORT;
 use getopts::Options;
 use std::env;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 fn print_usage(program: &str, opts: Options) {
     let brief = format!("Usage: {} [options] [package_dir]\nIf no arguments are given the GUI menu is used instead. (excluding -g)", program);
@@ -18,10 +19,17 @@ pub fn cli() -> CLIResults {
     opts.optopt("f",  "fighters",         "Use the fighters specified", "NAME1,NAME2,NAME3...");
     opts.optopt("h",  "humanplayers",     "Number of human players in the game", "NUM_HUMAN_PLAYERS");
     opts.optopt("c",  "cpuplayers",       "Number of CPU players in the game", "NUM_CPU_PLAYERS");
-    opts.optopt("a",  "address",          "IP Address of other client to start netplay with", "IP_ADDRESS");
+    opts.optopt("a",  "address",          "IP Addresses of other clients to start netplay with, the port defaults to 8413", "IP_ADDRESS1[:PORT],IP_ADDRESS2[:PORT]...");
+    opts.optopt("w",  "spectate",         "IP Address of a client to watch the netplay game of, the port defaults to 8413", "IP_ADDRESS[:PORT]");
+    opts.optopt("p",  "port",             "Port to listen for netplay connections on, overrides the config", "PORT");
     opts.optopt("n",  "netplayplayers",   "Search for a netplay game with the specified number of players", "NUM_PLAYERS");
     opts.optopt("r",  "netplayregion",    "Search for a netplay game with the specified region", "REGION");
-    opts.optopt("k",  "replay",           "load the replay in the replays folder with the specified filename. Replay additionally loads normally unused data that is kept specifically for hot reloading.", "FILENAME");
+    opts.optopt("k",  "replay",           "load the replay in the replays folder with the specified name, excluding the .replay extension", "NAME");
+    opts.optopt("v",  "verify",           "Resimulate the replay in the replays folder with the specified name and check every frame matches how it was originally played, then exit", "NAME");
+    opts.optflag("l", "hotreload",        "Continue from the snapshot saved by the save_hot_reload command, used by canon_collision_hot_reload");
+    opts.optopt("x",  "simulate",         "Simulate the match described by the json file as fast as possible without graphics, audio or controllers, then exit", "SETUP_FILE");
+    opts.optopt("e",  "exportframes",     "Render the debug view of every frame of the replay in the replays folder with the specified name to PNG files without a GPU, then exit", "NAME");
+    opts.optopt("o",  "output",           "Directory that --simulate and --exportframes write to, defaults to the current directory", "DIR");
     opts.optopt("m",  "maxhistoryframes", "The oldest history frame is removed when number of history frames exceeds this value", "NUM_FRAMES");
     opts.optopt("g",  "graphics",         "Graphics backend to use",
         if cfg!(feature = "wgpu_renderer") {
@@ -105,10 +113,34 @@ pub fn cli() -> CLIResults {
         results.continue_from = ContinueFrom::Game;
     }
 
-    if let Some(address) = matches.opt_str("a") {
-        if let Ok(address) = address.parse() {
-            results.address = Some(address);
-            results.continue_from = ContinueFrom::Netplay;
+    if let Some(addresses) = matches.opt_str("a") {
+        for address in addresses.split(',') {
+            if let Some(address) = parse_address(address) {
+                results.addresses.push(address);
+                results.continue_from = ContinueFrom::Netplay;
+            }
+            else {
+                print_usage(program, opts);
+                results.continue_from = ContinueFrom::Close;
+                return results;
+            }
+        }
+    }
+
+    if let Some(address) = matches.opt_str("w") {
+        if let Some(address) = parse_address(&address) {
+            results.continue_from = ContinueFrom::Spectate(address);
+        }
+        else {
+            print_usage(program, opts);
+            results.continue_from = ContinueFrom::Close;
+            return results;
+        }
+    }
+
+    if let Some(port) = matches.opt_str("p") {
+        if let Ok(port) = port.parse() {
+            results.netplay_port = Some(port);
         }
         else {
             print_usage(program, opts);
@@ -150,9 +182,39 @@ pub fn cli() -> CLIResults {
         results.continue_from = ContinueFrom::ReplayFile(replay_filename);
     }
 
+    if let Some(replay_filename) = matches.opt_str("v") {
+        results.continue_from = ContinueFrom::Verify(replay_filename);
+    }
+
+    if matches.opt_present("l") {
+        results.continue_from = ContinueFrom::HotReload;
+    }
+
+    if let Some(output) = matches.opt_str("o") {
+        results.simulation_output = Some(output);
+    }
+
+    if let Some(replay_filename) = matches.opt_str("e") {
+        results.continue_from = ContinueFrom::ExportFrames(replay_filename);
+    }
+
+    if let Some(setup_path) = matches.opt_str("x") {
+        results.continue_from = ContinueFrom::Simulate(setup_path);
+    }
+
     results
 }
 
+/// Parses an address with an optional port
+fn parse_address(address: &str) -> Option<SocketAddr> {
+    address.parse::<SocketAddr>().ok().or_else(|| {
+        address
+            .parse::<IpAddr>()
+            .ok()
+            .map(|ip| SocketAddr::new(ip, DEFAULT_PORT))
+    })
+}
+
 pub struct CLIResults {
     pub graphics_backend: GraphicsBackendChoice,
     pub package: Option<String>,
@@ -160,12 +222,14 @@ pub struct CLIResults {
     pub total_cpu_players: Option<usize>,
     pub fighter_names: Vec<String>,
     pub stage_name: Option<String>,
-    pub address: Option<IpAddr>,
+    pub addresses: Vec<SocketAddr>,
     pub continue_from: ContinueFrom,
     pub netplay_players: Option<u8>,
     pub netplay_region: Option<String>,
+    pub netplay_port: Option<u16>,
     pub debug: bool,
     pub max_history_frames: Option<usize>,
+    pub simulation_output: Option<String>,
 }
 
 impl CLIResults {
@@ -177,12 +241,14 @@ impl CLIResults {
             total_cpu_players: None,
             fighter_names: vec![],
             stage_name: None,
-            address: None,
+            addresses: vec![],
             continue_from: ContinueFrom::Menu,
             netplay_players: None,
             netplay_region: None,
+            netplay_port: None,
             debug: false,
             max_history_frames: None,
+            simulation_output: None,
         }
     }
 }
@@ -190,9 +256,17 @@ impl CLIResults {
 pub enum ContinueFrom {
     Menu,
     Netplay,
+    Spectate(SocketAddr),
     MatchMaking,
     Game,
     ReplayFile(String),
+    /// Name of a replay to verify
+    Verify(String),
+    /// Name of a replay to render to PNG files
+    ExportFrames(String),
+    HotReload,
+    /// Path to a SimulationSetup json file
+    Simulate(String),
     Close,
 }
 
@@ -53,11 +53,13 @@ pub struct Body {
     pub location: Location,
     pub face_right: bool,
     pub frames_since_ledge: u64,
+    /// Used to decide if the last hit caused a death
+    pub frames_since_hit: u64,
 
     // Only use for debug display
-    pub frames_since_hit: u64,
     pub hit_angle_pre_di: Option<f32>,
     pub hit_angle_post_di: Option<f32>,
+    pub hit_knockback: Option<f32>,
 }
 
 impl Body {
@@ -74,11 +76,12 @@ impl Body {
             frames_since_ledge: 0,
             location,
             face_right,
+            frames_since_hit: 0,
 
             // Only use for debug display
-            frames_since_hit: 0,
             hit_angle_pre_di: None,
             hit_angle_post_di: None,
+            hit_knockback: None,
         }
     }
 
@@ -619,6 +622,7 @@ impl Body {
         // debug data
         self.hit_angle_pre_di = Some(angle);
         self.hit_angle_post_di = None;
+        self.hit_knockback = Some(kb_vel);
         self.frames_since_hit = 0;
 
         let can_di = kb_vel >= 80.0 || self.is_airbourne() || (angle != 0.0 && angle != PI);
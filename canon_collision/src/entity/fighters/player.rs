@@ -11,12 +11,13 @@ use crate::graphics;
 use crate::particle::{Particle, ParticleType};
 use crate::results::{DeathRecord, RawPlayerResult};
 use crate::rules::{Goal, Rules};
+use crate::training::Combo;
 
 use canon_collision_lib::entity_def::item::ItemAction;
 use canon_collision_lib::entity_def::player::PlayerAction;
 use canon_collision_lib::entity_def::{EntityDef, HitBox, HitStun, HitboxEffect, HurtBox, Shield};
 use canon_collision_lib::geometry::Rect;
-use canon_collision_lib::input::state::PlayerInput;
+use canon_collision_lib::input::state::{ControllerInput, PlayerInput};
 use canon_collision_lib::package::Package;
 use canon_collision_lib::stage::{Stage, Surface};
 
@@ -26,6 +27,9 @@ use treeflection::KeyedContextVec;
 use std::f32;
 use std::f32::consts::PI;
 
+/// A death is credited to the last player to hit the dying player if the hit was this recent, otherwise it is a self-destruct
+const KILL_CREDIT_FRAMES: u64 = 300;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum LockTimer {
     Active(u64),
@@ -76,6 +80,7 @@ pub struct Player {
     pub land_frame_skip: u8,
     pub hitstun: f32,
     /// this is only used for end-game statistics so player id is fine
+    /// Cleared when landing or grabbing a ledge, as the player has recovered from the hit
     pub hit_by: Option<usize>,
     pub particles: Vec<Particle>,
     pub aerial_dodge_frame: Option<u64>,
@@ -2559,6 +2564,13 @@ impl Player {
         context
             .audio
             .play_sound_effect(context.entity_def, SfxType::Die);
+
+        // Hitting yourself with your own projectile is still a self-destruct
+        let killer = self
+            .hit_by
+            .filter(|x| *x != self.id && self.body.frames_since_hit <= KILL_CREDIT_FRAMES);
+        self.hit_by = None;
+
         self.body = if context.stage.respawn_points.len() == 0 {
             Body::new(Location::Airbourne { x: 0.0, y: 0.0 }, true)
         } else {
@@ -2581,7 +2593,7 @@ impl Player {
         self.hitstun = 0.0;
 
         self.result.deaths.push(DeathRecord {
-            player: self.hit_by,
+            player: killer,
             frame: game_frame,
         });
 
@@ -2600,7 +2612,9 @@ impl Player {
                     None
                 }
             }
-            Goal::KillDeathScore => ActionResult::set_action(PlayerAction::ReSpawn),
+            Goal::KillDeathScore | Goal::Training => {
+                ActionResult::set_action(PlayerAction::ReSpawn)
+            }
         }
     }
 
@@ -2839,6 +2853,8 @@ impl Player {
             damage: self.body.damage,
             stocks: self.stocks,
             shield,
+            input_display: None,
+            combo: None,
         }
     }
 
@@ -2967,6 +2983,16 @@ pub struct RenderPlayer {
     pub damage: f32,
     pub stocks: Option<u64>,
     pub shield: Option<RenderShield>,
+    pub input_display: Option<RenderInputDisplay>,
+    /// Only used in training mode
+    pub combo: Option<Combo>,
+}
+
+/// The inputs used by a player to reach the current frame, drawn as a controller overlay
+#[derive(Clone)]
+pub struct RenderInputDisplay {
+    /// Taken from PlayerInput::history, the first element is the input of the current frame
+    pub history: Vec<ControllerInput>,
 }
 
 pub struct RenderShield {
@@ -206,6 +206,7 @@ impl Entity {
             if body.frames_since_hit > 60 {
                 body.hit_angle_pre_di = None;
                 body.hit_angle_post_di = None;
+                body.hit_knockback = None;
             }
         }
 
@@ -1,35 +1,44 @@
+use crate::ai::TrainingDummy;
 use crate::audio::{Audio, BGMMetadata};
 use crate::camera::Camera;
 use crate::collision::collision_box;
 use crate::collision::item_grab;
 use crate::entity::components::action_state::ActionState;
-use crate::entity::fighters::player::Player;
+use crate::entity::fighters::player::{Player, RenderInputDisplay};
 use crate::entity::fighters::toriel::Toriel;
 use crate::entity::fighters::Fighter;
 use crate::entity::{
-    DebugEntities, DebugEntity, Entities, Entity, EntityKey, EntityType, RenderEntity, StepContext,
+    DebugEntities, DebugEntity, Entities, Entity, EntityKey, EntityType, RenderEntity,
+    RenderEntityType, StepContext,
 };
 use crate::graphics::{GraphicsMessage, Render, RenderType};
+use crate::hot_reload::{self, HotReloadSnapshot};
 use crate::menu::ResumeMenu;
+use crate::replay_events::{self, ReplayEvent};
 use crate::replays;
 use crate::replays::Replay;
-use crate::results::{GameResults, PlayerResult, RawPlayerResult};
+use crate::results::{self, DeathRecord, GameResults, PlayerResult, RawPlayerResult};
 use crate::rules::{Goal, Rules};
+use crate::training::Training;
+use crate::verify;
 
 use canon_collision_lib::command_line::CommandLine;
 use canon_collision_lib::config::Config;
 use canon_collision_lib::entity_def::player::PlayerAction;
 use canon_collision_lib::entity_def::{ActionFrame, CollisionBox, EntityDefType, FighterType};
+use canon_collision_lib::files;
 use canon_collision_lib::geometry::Rect;
 use canon_collision_lib::input::state::{ControllerInput, PlayerInput};
 use canon_collision_lib::input::Input;
-use canon_collision_lib::network::Netplay;
+use canon_collision_lib::network::{self, Netplay, RollbackBuffer};
 use canon_collision_lib::package::Package;
+pub use canon_collision_lib::replays_files::PlayerSetup;
 use canon_collision_lib::stage::{DebugStage, Floor, RenderStageMode, SpawnPoint, Stage, Surface};
 
 use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::fmt;
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -44,6 +53,9 @@ use winit_input_helper::WinitInputHelper;
 #[derive(Clone, Default, Serialize, Deserialize, Node)]
 #[NodeActions(
     NodeAction(function = "save_replay", return_string),
+    NodeAction(function = "save_hot_reload", return_string),
+    NodeAction(function = "verify_replay", return_string),
+    NodeAction(function = "take_control", return_string),
     NodeAction(function = "reset_deadzones", return_string),
     NodeAction(function = "copy_stage_to_package", return_string),
     NodeAction(function = "copy_package_to_stage", return_string)
@@ -64,8 +76,12 @@ pub struct Game {
     debug_entities: DebugEntities,
     pub selected_controllers: Vec<usize>,
     pub selected_ais: Vec<usize>,
+    /// Options used by CPU players with the Training Dummy AI profile
+    pub training_dummy: TrainingDummy,
     pub selected_stage: String,
     pub rules: Rules,
+    /// Only used when the goal is Goal::Training
+    pub training: Training,
     edit: Edit,
     pub debug_output_this_step: bool,
     pub debug_lines: Vec<String>,
@@ -73,10 +89,40 @@ pub struct Game {
     copied_frame: Option<ActionFrame>,
     pub camera: Camera,
     pub tas: Vec<ControllerInput>,
+    /// Sorted by frame
+    pub events: Vec<ReplayEvent>,
     bgm_metadata: Option<BGMMetadata>,
     save_replay: bool,
+    save_hot_reload: bool,
+    verify_replay: bool,
+    take_control: bool,
+    /// Draw a controller overlay for every player
+    pub input_display: bool,
+    /// Indexed by player id
+    #[serde(skip)]
+    input_displays: Vec<Option<RenderInputDisplay>>,
     reset_deadzones: bool,
     prev_mouse_point: Option<(f32, f32)>,
+    #[serde(skip)]
+    rollback_snapshots: RollbackBuffer<RollbackSnapshot>,
+    /// The next netplay frame to have its state hash compared with peers
+    #[serde(skip)]
+    state_hash_frame: usize,
+}
+
+/// The state of the game before a frame is stepped, used to roll back netplay mispredictions.
+#[derive(Clone)]
+struct RollbackSnapshot {
+    entities: Entities,
+    stage: Stage,
+}
+
+/// The local game state on the first frame it differed from a peers game state
+#[derive(Serialize)]
+struct DesyncState<'a> {
+    frame: usize,
+    entities: &'a Entities,
+    stage: &'a Stage,
 }
 
 /// Frame 0 refers to the initial state of the game.
@@ -158,7 +204,9 @@ impl Game {
             deleted_history_frames: setup.deleted_history_frames,
             selected_controllers: setup.controllers,
             selected_ais: setup.ais,
+            training_dummy: TrainingDummy::default(),
             selected_stage: setup.stage,
+            training: Training::new(setup.players.len()),
             rules: setup.rules,
             edit: setup.edit,
             debug_output_this_step: false,
@@ -167,9 +215,17 @@ impl Game {
             copied_frame: None,
             camera: setup.camera,
             tas: vec![],
+            events: setup.events,
             save_replay: false,
+            save_hot_reload: false,
+            verify_replay: false,
+            take_control: false,
+            input_display: false,
+            input_displays: vec![],
             reset_deadzones: false,
             prev_mouse_point: None,
+            rollback_snapshots: Default::default(),
+            state_hash_frame: 0,
             bgm_metadata,
             package,
             stage,
@@ -186,7 +242,7 @@ impl Game {
         input: &mut Input,
         os_input: &WinitInputHelper,
         os_input_blocked: bool,
-        netplay: &Netplay,
+        netplay: &mut Netplay,
         audio: &mut Audio,
     ) -> GameState {
         if os_input.held_alt() && os_input.key_pressed_os(VirtualKeyCode::Return) {
@@ -195,10 +251,27 @@ impl Game {
         }
 
         if self.save_replay {
-            replays::save_replay(&Replay::new(self, input));
+            replays::save_replay(&Replay::new(self, input), config);
             self.save_replay = false;
         }
 
+        if self.save_hot_reload {
+            if let Err(err) = hot_reload::save_snapshot(&HotReloadSnapshot::new(self, input)) {
+                error!("Failed to save hot reload snapshot: {}", err);
+            }
+            self.save_hot_reload = false;
+        }
+
+        if self.verify_replay {
+            verify::log_verify_game(self, input);
+            self.verify_replay = false;
+        }
+
+        if self.take_control {
+            self.take_control_from_current_frame(input);
+            self.take_control = false;
+        }
+
         {
             let state = self.state.clone();
             match state {
@@ -215,8 +288,12 @@ impl Game {
             }
 
             if !os_input_blocked {
+                if os_input.key_pressed_os(VirtualKeyCode::Tab) {
+                    self.input_display = !self.input_display;
+                }
+
                 match state {
-                    GameState::Local                     => self.step_local_os_input(os_input),
+                    GameState::Local                     => self.step_local_os_input(input, os_input),
                     GameState::ReplayForwardsFromHistory => self.step_replay_forwards_os_input(os_input),
                     GameState::ReplayForwardsFromInput   => self.step_replay_forwards_os_input(os_input),
                     GameState::ReplayBackwards           => self.step_replay_backwards_os_input(os_input),
@@ -237,6 +314,7 @@ impl Game {
             );
 
             self.generate_debug(input, netplay);
+            self.generate_input_displays(input, netplay);
         }
 
         self.set_context();
@@ -265,11 +343,39 @@ impl Game {
     }
 
     pub fn save_replay(&mut self) -> String {
+        if let Goal::Training = self.rules.goal {
+            return String::from(
+                "Training games cannot be saved as replays, the training settings are not recorded",
+            );
+        }
         self.save_replay = true;
         // TODO: We are actually lying here, we cant complete the save until the Game::step where we have access to the input data.
         String::from("Save replay completed")
     }
 
+    pub fn save_hot_reload(&mut self) -> String {
+        self.save_hot_reload = true;
+        // Like save_replay, the save is completed during the next Game::step
+        String::from("Save hot reload snapshot completed")
+    }
+
+    pub fn verify_replay(&mut self) -> String {
+        if let Goal::Training = self.rules.goal {
+            return String::from(
+                "Training games cannot be verified, the training settings are not recorded",
+            );
+        }
+        self.verify_replay = true;
+        // Like save_replay, the verification is run during the next Game::step
+        String::from("Replay verification started, the result will be logged")
+    }
+
+    pub fn take_control(&mut self) -> String {
+        self.take_control = true;
+        // Like save_replay, the history is truncated during the next Game::step
+        String::from("Resuming local play from the current frame")
+    }
+
     pub fn reset_deadzones(&mut self) -> String {
         self.reset_deadzones = true;
         String::from("Deadzones reset")
@@ -333,7 +439,8 @@ impl Game {
         }
     }
 
-    fn step_local(&mut self, input: &mut Input, netplay: &Netplay, audio: &mut Audio) {
+    /// Record the current state into the history and advance to the next frame, erasing any history after it.
+    fn advance_history(&mut self) {
         self.entity_history.push(self.entities.clone());
         self.stage_history.push(self.stage.clone());
         self.current_frame += 1;
@@ -345,6 +452,14 @@ impl Game {
         for _ in self.current_history_index()..self.stage_history.len() {
             self.stage_history.pop();
         }
+    }
+
+    fn step_local(&mut self, input: &mut Input, netplay: &Netplay, audio: &mut Audio) {
+        self.advance_history();
+
+        // New inputs are used from this frame on, so later events may no longer occur
+        let current_frame = self.current_frame;
+        self.events.retain(|x| x.frame < current_frame);
 
         // run game loop
         input.game_update(self.current_frame);
@@ -366,40 +481,159 @@ impl Game {
         }
     }
 
-    fn step_local_os_input(&mut self, os_input: &WinitInputHelper) {
+    fn step_local_os_input(&mut self, input: &mut Input, os_input: &WinitInputHelper) {
         if os_input.key_pressed_os(VirtualKeyCode::Space)
             || os_input.key_pressed_os(VirtualKeyCode::Return)
         {
             self.state = GameState::Paused;
         }
+
+        if let Goal::Training = self.rules.goal {
+            if os_input.key_pressed_os(VirtualKeyCode::F5) {
+                self.training.snapshot_frame = Some(self.current_frame);
+                info!("Saved training snapshot at frame {}", self.current_frame);
+            } else if os_input.key_pressed_os(VirtualKeyCode::F6) {
+                self.restore_training_snapshot(input);
+            }
+
+            let damage_change = if os_input.held_shift() { 1.0 } else { 10.0 };
+            if os_input.key_pressed_os(VirtualKeyCode::F7) {
+                self.training.select_next_player();
+                info!(
+                    "Selected player {} for training damage",
+                    self.training.selected_player + 1
+                );
+            } else if os_input.key_pressed_os(VirtualKeyCode::F8) {
+                if let Some(damage) = self.training.change_damage(&self.entities, -damage_change) {
+                    info!("Set training damage to {}%", damage);
+                }
+            } else if os_input.key_pressed_os(VirtualKeyCode::F9) {
+                if let Some(damage) = self.training.change_damage(&self.entities, damage_change) {
+                    info!("Set training damage to {}%", damage);
+                }
+            } else if os_input.key_pressed_os(VirtualKeyCode::F10) {
+                if let Some(locked) = self.training.toggle_lock_damage(&self.entities) {
+                    info!("Training damage locked: {}", locked);
+                }
+            }
+        }
     }
 
-    fn step_netplay(&mut self, input: &mut Input, netplay: &Netplay, audio: &mut Audio) {
+    /// Returns to the state saved by the training snapshot hotkey, discarding everything that happened after it
+    fn restore_training_snapshot(&mut self, input: &mut Input) {
+        if let Some(frame) = self.training.snapshot_frame {
+            // The snapshot is lost if its frame has been removed from the history
+            if frame >= self.deleted_history_frames && frame < self.current_frame {
+                self.jump_frame(frame);
+                self.take_control_from_current_frame(input);
+                self.training.reset_combos();
+            }
+        }
+    }
+
+    fn step_netplay(&mut self, input: &mut Input, netplay: &mut Netplay, audio: &mut Audio) {
         if !netplay.skip_frame() {
             self.current_frame += 1;
 
-            let start = self.current_frame - netplay.frames_to_step();
+            let start = self.current_frame.saturating_sub(netplay.frames_to_step());
             let end = self.current_frame;
 
+            // Only need to restore state when rolling back to a mispredicted frame
+            if netplay.frames_to_step() > 1 {
+                if let Some(snapshot) = self.rollback_snapshots.get(start) {
+                    self.entities = snapshot.entities.clone();
+                    self.stage = snapshot.stage.clone();
+                } else if let (Some(entities), Some(stage)) = (
+                    start
+                        .checked_sub(1)
+                        .and_then(|i| self.entity_history.get(i)),
+                    start.checked_sub(1).and_then(|i| self.stage_history.get(i)),
+                ) {
+                    // The history holds the state after each frame, which is the state before the following frame
+                    error!(
+                        "No rollback snapshot for frame {}, restoring it from the history instead",
+                        start
+                    );
+                    self.entities = entities.clone();
+                    self.stage = stage.clone();
+                } else {
+                    error!("No rollback snapshot or history for frame {}", start);
+                    netplay.disconnect_with_reason(&format!(
+                        "Failed to roll back to frame {}, the game state is no longer available",
+                        start
+                    ));
+                    return;
+                }
+            }
             self.entity_history.truncate(start);
             self.stage_history.truncate(start);
-            if start != 0 {
-                self.entities = self.entity_history.get(start - 1).unwrap().clone();
-                self.stage = self.stage_history.get(start - 1).unwrap().clone();
-            }
+            self.events.retain(|x| x.frame < start);
 
             input.netplay_update();
 
             for frame in start..end {
+                self.rollback_snapshots.push(
+                    frame,
+                    RollbackSnapshot {
+                        entities: self.entities.clone(),
+                        stage: self.stage.clone(),
+                    },
+                );
+
                 let player_inputs = &input.players(frame, netplay);
+                input.record_netplay_frame(frame, player_inputs);
                 self.step_game(input, player_inputs, audio);
 
                 self.entity_history.push(self.entities.clone());
                 self.stage_history.push(self.stage.clone());
             }
+
+            // Confirmed frames can no longer be rolled back so their state must match every peers state.
+            // Netplay counts frames from the start of the session, not the start of the game.
+            let frame_origin = netplay.frame_origin();
+            let confirmed_frames = netplay
+                .confirmed_frames()
+                .saturating_sub(frame_origin)
+                .min(self.entity_history.len());
+            while self.state_hash_frame < confirmed_frames {
+                let frame = self.state_hash_frame;
+                let hash =
+                    network::state_hash(&(&self.entity_history[frame], &self.stage_history[frame]));
+                netplay.add_state_hash(frame_origin + frame, hash);
+                self.state_hash_frame += 1;
+            }
+
+            if let Some(session_frame) = netplay.desync_frame() {
+                let frame = session_frame.saturating_sub(frame_origin);
+                let path = self.save_desync_state(frame);
+                netplay.disconnect_with_reason(&format!(
+                    "Desynced from peer on frame {}, the local game state was saved to {}",
+                    frame,
+                    path.to_str().unwrap()
+                ));
+            }
         }
     }
 
+    /// Save the game state of the specified frame so it can be diffed against the state saved by the desynced peer
+    fn save_desync_state(&self, frame: usize) -> PathBuf {
+        let mut path = files::get_path();
+        path.push("desyncs");
+        path.push(format!(
+            "{} frame {}.json",
+            Local::now().format("%Y-%m-%d %H-%M-%S"),
+            frame
+        ));
+
+        let desync_state = DesyncState {
+            frame,
+            entities: &self.entity_history[frame],
+            stage: &self.stage_history[frame],
+        };
+        files::save_struct_json(&path, &desync_state);
+        path
+    }
+
     fn step_pause(&mut self, input: &mut Input) {
         if input.game_quit_held() {
             self.state = GameState::Quit(ResumeMenu::Unchanged);
@@ -434,6 +668,12 @@ impl Game {
             self.saved_frame = self.current_frame;
         } else if os_input.key_pressed_os(VirtualKeyCode::I) {
             self.jump_frame(self.saved_frame);
+        } else if os_input.key_pressed_os(VirtualKeyCode::Y) {
+            self.jump_to_previous_event();
+        } else if os_input.key_pressed_os(VirtualKeyCode::O) {
+            self.jump_to_next_event(input, netplay);
+        } else if os_input.key_pressed_os(VirtualKeyCode::P) {
+            self.take_control_from_current_frame(input);
         } else if os_input.key_pressed_os(VirtualKeyCode::Return) {
             self.state = GameState::Local;
         }
@@ -1227,7 +1467,8 @@ impl Game {
         audio: &mut Audio,
     ) {
         if self.current_frame <= input.last_frame() {
-            self.current_frame += 1;
+            // Replays only store inputs, so the history is reconstructed as the replay is played
+            self.advance_history();
             let player_inputs = &input.players(self.current_frame, netplay);
             self.step_game(input, player_inputs, audio);
 
@@ -1309,6 +1550,64 @@ impl Game {
         }
     }
 
+    /// Discards everything that happened after the current frame and resumes local play with the live controllers.
+    /// Used to practice a situation from a replay or to retry from an earlier point in a local game.
+    fn take_control_from_current_frame(&mut self, input: &mut Input) {
+        if let GameState::Netplay = self.state {
+            error!("Cannot take control of a netplay game");
+            return;
+        }
+
+        let history_index = self.current_history_index();
+        self.entity_history.truncate(history_index);
+        self.stage_history.truncate(history_index);
+        input.truncate_history(self.current_frame);
+        let current_frame = self.current_frame;
+        self.events.retain(|x| x.frame <= current_frame);
+
+        self.state = GameState::Local;
+        self.update_frame();
+        info!("Resumed local play from frame {}", self.current_frame);
+    }
+
+    /// Replaces the events previously recorded for the frame
+    fn record_events(&mut self, frame: usize, events: Vec<ReplayEvent>) {
+        self.events.retain(|x| x.frame != frame);
+        let index = self.events.partition_point(|x| x.frame <= frame);
+        self.events.splice(index..index, events);
+    }
+
+    fn jump_to_previous_event(&mut self) {
+        if let Some(event) = self
+            .events
+            .iter()
+            .rev()
+            .find(|x| x.frame < self.current_frame)
+        {
+            self.jump_frame(event.frame);
+        }
+    }
+
+    /// Jumps through the history to the next event.
+    /// If the event is later than the history, the game is resimulated from the input history until it is reached.
+    fn jump_to_next_event(&mut self, input: &mut Input, netplay: &Netplay) {
+        let frame = match self.events.iter().find(|x| x.frame > self.current_frame) {
+            Some(event) => event.frame,
+            None => return,
+        };
+
+        if frame - self.deleted_history_frames < self.entity_history.len() {
+            self.jump_frame(frame);
+        } else {
+            // Sound effects would all play at once
+            let mut audio = Audio::muted();
+            while self.current_frame < frame && self.current_frame <= input.last_frame() {
+                self.step_replay_forwards_from_input(input, netplay, &mut audio);
+            }
+            self.state = GameState::Paused;
+        }
+    }
+
     fn get_seed(&self) -> [u8; 32] {
         let mut seed = [0; 32];
         (&mut seed[0..8])
@@ -1516,6 +1815,17 @@ impl Game {
                 collision_entities.insert(entity);
             }
 
+            // The state after stepping is the next history frame
+            let frame = self.deleted_history_frames + self.entity_history.len();
+            let events = replay_events::detect_events(frame, &self.entities, &collision_entities);
+            self.record_events(frame, events);
+
+            if let Goal::Training = self.rules.goal {
+                self.training
+                    .update_combos(&self.entities, &collision_entities);
+                self.training.apply_damage(&mut collision_entities);
+            }
+
             self.entities = collision_entities;
         }
 
@@ -1560,6 +1870,35 @@ impl Game {
             .players_iter()
             .map(|(player, state)| player.result(state))
             .collect();
+        let player_ids: Vec<usize> = self.players_iter().map(|(player, _)| player.id).collect();
+
+        // Deaths record who killed the player, so the kills of each player are found in everyone elses deaths
+        let kills: Vec<Vec<DeathRecord>> = player_ids
+            .iter()
+            .map(|killer| {
+                let mut kills = vec![];
+                for (victim, raw_player_result) in player_ids.iter().zip(raw_player_results.iter())
+                {
+                    for death in &raw_player_result.deaths {
+                        if death.player == Some(*killer) {
+                            kills.push(DeathRecord {
+                                player: Some(*victim),
+                                frame: death.frame,
+                            });
+                        }
+                    }
+                }
+                kills
+            })
+            .collect();
+        let scores: Vec<i64> = kills
+            .iter()
+            .zip(raw_player_results.iter())
+            .map(|(kills, raw_player_result)| {
+                results::kill_death_score(kills, &raw_player_result.deaths)
+            })
+            .collect();
+
         // TODO: Players on the same team score to the same pool and share their place.
         let places: Vec<usize> = match self.rules.goal {
             Goal::LastManStanding => {
@@ -1594,24 +1933,21 @@ impl Game {
                 raw_player_results_i.iter().map(|x| x.0).collect()
             }
             Goal::KillDeathScore => {
-                // highest kills wins
+                // highest score wins
                 // tie breaker: least deaths wins
                 let mut raw_player_results_i: Vec<(usize, &RawPlayerResult)> =
                     raw_player_results.iter().enumerate().collect();
                 raw_player_results_i.sort_by(|a_set, b_set| {
-                    // Repopulating kill lists every frame shouldnt be too bad
-                    let a_kills: Vec<usize> = vec![]; // TODO: populate
-                    let b_kills: Vec<usize> = vec![]; // TODO: populate
-                    let a = a_set.1;
-                    let b = b_set.1;
-                    let a_kills = a_kills.len();
-                    let b_kills = b_kills.len();
-                    let a_deaths = a.deaths.len();
-                    let b_deaths = b.deaths.len();
-                    b_kills.cmp(&a_kills).then(a_deaths.cmp(&b_deaths))
+                    let a_score = scores[a_set.0];
+                    let b_score = scores[b_set.0];
+                    let a_deaths = a_set.1.deaths.len();
+                    let b_deaths = b_set.1.deaths.len();
+                    b_score.cmp(&a_score).then(a_deaths.cmp(&b_deaths))
                 });
                 raw_player_results_i.iter().map(|x| x.0).collect()
             }
+            // nobody wins training
+            Goal::Training => (0..raw_player_results.len()).collect(),
         };
 
         let mut player_results: Vec<PlayerResult> = vec![];
@@ -1625,15 +1961,21 @@ impl Game {
                 fighter: raw_player_result.ended_as_fighter.clone().unwrap(),
                 team: raw_player_result.team,
                 controller: self.selected_controllers[i],
-                place: places[i],
-                kills: vec![], // TODO
+                // places is ordered from first to last place
+                place: places.iter().position(|x| *x == i).unwrap(),
+                kills: kills[i].clone(),
                 deaths: raw_player_result.deaths.clone(),
+                score: match self.rules.goal {
+                    Goal::KillDeathScore => Some(scores[i]),
+                    _ => None,
+                },
                 lcancel_percent,
             });
         }
         player_results.sort_by_key(|x| x.place);
 
-        let replay = Replay::new(self, input);
+        let mut replay = Replay::new(self, input);
+        replay.metadata.winner = places.first().cloned();
 
         GameState::Quit(ResumeMenu::Results(GameResults {
             player_results,
@@ -1671,6 +2013,23 @@ impl Game {
         }
     }
 
+    /// Uses the same inputs as generate_debug so the display matches the current frame, even when moving through history.
+    fn generate_input_displays(&mut self, input: &Input, netplay: &Netplay) {
+        self.input_displays.clear();
+        if self.input_display {
+            let player_inputs = &input.players_no_log(self.current_frame, netplay);
+            self.input_displays = self
+                .selected_controllers
+                .iter()
+                .map(|x| {
+                    player_inputs.get(*x).map(|input| RenderInputDisplay {
+                        history: input.history.clone(),
+                    })
+                })
+                .collect();
+        }
+    }
+
     /// Call this whenever an entity's frame is changed, this can be from:
     /// *   the fighter's frame data is changed
     /// *   the entity now refers to a different frame.
@@ -1721,7 +2080,7 @@ impl Game {
                 }
             }
 
-            let player_render = entity.render(
+            let mut player_render = entity.render(
                 selected_colboxes,
                 entity_selected,
                 debug,
@@ -1731,6 +2090,15 @@ impl Game {
                 entity_defs,
                 surfaces,
             );
+            if let RenderEntityType::Player(player) = &mut player_render.render_type {
+                player.input_display = entity
+                    .player_id()
+                    .and_then(|x| self.input_displays.get(x).cloned())
+                    .flatten();
+                if let Goal::Training = self.rules.goal {
+                    player.combo = entity.player_id().and_then(|x| self.training.combo(x));
+                }
+            }
             render_entities.push(RenderObject::Entity(player_render));
         }
 
@@ -1821,6 +2189,27 @@ impl Game {
         }
     }
 
+    /// Deterministically hashes the state of the current frame
+    pub fn state_hash(&self) -> u32 {
+        network::state_hash(&(&self.entities, &self.stage))
+    }
+
+    /// Hashes the state of every frame from frame 0 to the current frame.
+    /// Returns an empty Vec if the earliest frames have been removed from the history.
+    pub fn state_hashes(&self) -> Vec<u32> {
+        if self.deleted_history_frames > 0 {
+            return vec![];
+        }
+        let mut hashes: Vec<u32> = self
+            .entity_history
+            .iter()
+            .zip(self.stage_history.iter())
+            .map(|state| network::state_hash(&state))
+            .collect();
+        hashes.push(self.state_hash());
+        hashes
+    }
+
     pub fn current_history_index(&self) -> usize {
         self.current_frame - self.deleted_history_frames
     }
@@ -1844,6 +2233,27 @@ impl Game {
     pub fn entities(&self) -> Entities {
         self.entities.clone()
     }
+    /// The entities as they were the specified number of frames ago, limited to the oldest frame in history
+    pub fn entities_frames_ago(&self, frames: usize) -> &Entities {
+        if frames == 0 {
+            &self.entities
+        } else {
+            let index = self.current_history_index().saturating_sub(frames);
+            self.entity_history.get(index).unwrap_or(&self.entities)
+        }
+    }
+    pub fn selected_players(&self) -> Vec<PlayerSetup> {
+        let mut selected_players = vec![];
+        for (_, entity) in &self.entities {
+            if let Some(fighter) = entity.ty.get_player() {
+                selected_players.push(PlayerSetup {
+                    fighter: entity.state.entity_def_key.clone(),
+                    team: fighter.team,
+                });
+            }
+        }
+        selected_players
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Node)]
@@ -2088,6 +2498,7 @@ pub struct GameSetup {
     //       or maybe we should even rewrite to have a single Option<HotReload> field
     pub hot_reload_entities: Option<Entities>,
     pub hot_reload_stage: Option<Stage>,
+    pub events: Vec<ReplayEvent>,
     pub edit: Edit,
 }
 
@@ -2096,9 +2507,3 @@ impl GameSetup {
         Local::now().timestamp() as u64
     }
 }
-
-#[derive(Clone, Default, Serialize, Deserialize, Node)]
-pub struct PlayerSetup {
-    pub fighter: String,
-    pub team: usize,
-}
@@ -0,0 +1,116 @@
+use crate::camera::Camera;
+use crate::entity::{DebugEntities, Entities};
+use crate::game::{Edit, Game, GameSetup, GameState, PlayerSetup};
+use crate::replay_events::ReplayEvent;
+use crate::rules::Rules;
+
+use canon_collision_lib::files;
+use canon_collision_lib::input::state::ControllerInput;
+use canon_collision_lib::input::Input;
+use canon_collision_lib::stage::{DebugStage, Stage};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+pub fn load_snapshot() -> Result<HotReloadSnapshot, String> {
+    let file = File::open(files::get_hot_reload_path()).map_err(|x| x.to_string())?;
+    bincode::deserialize_from(DeflateDecoder::new(BufReader::new(file))).map_err(|x| x.to_string())
+}
+
+/// The snapshot is compressed because consecutive frames of entity_history are mostly identical
+pub fn save_snapshot(snapshot: &HotReloadSnapshot) -> Result<(), String> {
+    let file = File::create(files::get_hot_reload_path()).map_err(|x| x.to_string())?;
+    let mut encoder = DeflateEncoder::new(BufWriter::new(file), Compression::fast());
+    bincode::serialize_into(&mut encoder, snapshot).map_err(|x| x.to_string())?;
+    encoder.finish().map_err(|x| x.to_string())?;
+    Ok(())
+}
+
+/// Contains the entire game state, including the editor and debug state, so that a rebuilt game can continue exactly where it left off.
+/// Unlike a Replay, this is only expected to be loaded by the next build of the same source.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HotReloadSnapshot {
+    pub init_seed: u64,
+    pub input_history: Vec<Vec<ControllerInput>>,
+    pub entity_history: Vec<Entities>,
+    pub stage_history: Vec<Stage>,
+    pub selected_controllers: Vec<usize>,
+    pub selected_players: Vec<PlayerSetup>,
+    pub selected_ais: Vec<usize>,
+    pub selected_stage: String,
+    pub rules: Rules,
+    pub max_history_frames: Option<usize>,
+    pub deleted_history_frames: usize,
+    pub current_frame: usize,
+    pub camera: Camera,
+    pub debug_entities: DebugEntities,
+    pub debug_stage: DebugStage,
+    pub entities: Entities,
+    pub stage: Stage,
+    pub as_running: bool,
+    pub edit: Edit,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl HotReloadSnapshot {
+    pub fn new(game: &Game, input: &Input) -> HotReloadSnapshot {
+        HotReloadSnapshot {
+            init_seed: game.init_seed,
+            input_history: input.get_history(),
+            entity_history: game.entity_history(),
+            stage_history: game.stage_history.clone(),
+            selected_controllers: game.selected_controllers.clone(),
+            selected_players: game.selected_players(),
+            selected_ais: game.selected_ais.clone(),
+            selected_stage: game.selected_stage.clone(),
+            rules: game.rules.clone(),
+            max_history_frames: game.max_history_frames,
+            deleted_history_frames: game.deleted_history_frames,
+            current_frame: game.current_frame,
+            camera: game.camera.clone(),
+            debug_entities: game.debug_entities(),
+            debug_stage: game.debug_stage.clone(),
+            entities: game.entities(),
+            stage: game.stage.clone(),
+            as_running: matches!(game.state, GameState::Local),
+            edit: game.edit(),
+            events: game.events.clone(),
+        }
+    }
+
+    pub fn into_game_setup(self) -> GameSetup {
+        let state = if self.as_running {
+            GameState::Local
+        } else {
+            GameState::Paused
+        };
+
+        GameSetup {
+            init_seed: self.init_seed,
+            input_history: self.input_history,
+            entity_history: self.entity_history,
+            stage_history: self.stage_history,
+            controllers: self.selected_controllers,
+            players: self.selected_players,
+            ais: self.selected_ais,
+            stage: self.selected_stage,
+            rules: self.rules,
+            max_history_frames: self.max_history_frames,
+            deleted_history_frames: self.deleted_history_frames,
+            edit: self.edit,
+            current_frame: self.current_frame,
+            debug: false,
+            camera: self.camera,
+            debug_entities: Some(self.debug_entities),
+            debug_stage: Some(self.debug_stage),
+            hot_reload_entities: Some(self.entities),
+            hot_reload_stage: Some(self.stage),
+            events: self.events,
+            state,
+        }
+    }
+}
@@ -20,20 +20,27 @@ pub(crate) mod collision;
 pub(crate) mod entity;
 pub(crate) mod game;
 pub(crate) mod graphics;
+pub(crate) mod hot_reload;
 pub(crate) mod menu;
 pub(crate) mod particle;
+pub(crate) mod replay_events;
 pub(crate) mod replays;
 pub(crate) mod results;
 pub(crate) mod rules;
+pub(crate) mod simulate;
+pub(crate) mod software_renderer;
+pub(crate) mod training;
+pub(crate) mod verify;
 
 #[cfg(feature = "wgpu_renderer")]
 pub(crate) mod wgpu;
 
-use crate::cli::GraphicsBackendChoice;
+use crate::cli::{ContinueFrom, GraphicsBackendChoice};
 #[cfg(feature = "wgpu_renderer")]
 use crate::wgpu::WgpuGraphics;
 use canon_collision_lib::logger;
 
+use std::path::Path;
 use winit::event_loop::EventLoop;
 
 fn main() {
@@ -41,6 +48,30 @@ fn main() {
     logger::init();
 
     let cli_results = cli::cli();
+    if let ContinueFrom::Simulate(setup_path) = &cli_results.continue_from {
+        let output_dir = cli_results.simulation_output.as_deref().unwrap_or(".");
+        if let Err(err) = simulate::run(Path::new(setup_path), Path::new(output_dir)) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let ContinueFrom::ExportFrames(name) = &cli_results.continue_from {
+        let output_dir = cli_results.simulation_output.as_deref().unwrap_or(".");
+        if let Err(err) = software_renderer::export_replay(name, Path::new(output_dir)) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let ContinueFrom::Verify(name) = &cli_results.continue_from {
+        if let Err(err) = verify::run_replay(name) {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let graphics_backend = cli_results.graphics_backend.clone();
     let (event_tx, render_rx) = app::run_in_thread(cli_results);
 
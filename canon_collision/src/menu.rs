@@ -1,3 +1,4 @@
+use crate::ai;
 use crate::audio::Audio;
 use crate::camera::Camera;
 use crate::game::{Edit, GameSetup, GameState, PlayerSetup};
@@ -5,14 +6,15 @@ use crate::graphics;
 use crate::graphics::{GraphicsMessage, Render, RenderType};
 use crate::replays;
 use crate::results::{GameResults, PlayerResult};
+use crate::rules::Rules;
 
 use canon_collision_lib::command_line::CommandLine;
 use canon_collision_lib::config::Config;
 use canon_collision_lib::input::state::PlayerInput;
 use canon_collision_lib::input::Input;
-use canon_collision_lib::network::{Netplay, NetplayState};
+use canon_collision_lib::network::{Netplay, NetplayState, RollbackBuffer};
 use canon_collision_lib::package::Package;
-use canon_collision_lib::replays_files;
+use canon_collision_lib::replays_files::{self, ReplayListing};
 
 use treeflection::{Node, NodeRunner, NodeToken};
 use winit::event::VirtualKeyCode;
@@ -33,12 +35,14 @@ pub struct Menu {
     prev_state: Option<MenuState>, // Only populated when the current state specifically needs to jump back to the previous state i.e we could arrive at the current state via multiple sources.
     fighter_selections: Vec<PlayerSelect>,
     game_ticker: MenuTicker,
+    /// Set when the training game mode is selected, so the game is started with training rules
+    training: bool,
     stage_ticker: Option<MenuTicker>, // Uses an option because we dont know how many stages there are at Menu creation, but we want to remember which stage was selected
     current_frame: usize,
     back_counter_max: usize,
     game_setup: Option<GameSetup>,
     game_results: Option<GameResults>,
-    netplay_history: Vec<NetplayHistory>,
+    netplay_history: RollbackBuffer<NetplayHistory>,
 }
 
 pub struct NetplayHistory {
@@ -55,12 +59,13 @@ impl Menu {
             prev_state: None,
             fighter_selections: vec![],
             stage_ticker: None,
-            game_ticker: MenuTicker::new(3),
+            game_ticker: MenuTicker::new(4),
+            training: false,
             current_frame: 0,
             back_counter_max: 90,
             game_setup: None,
             game_results: None,
-            netplay_history: vec![],
+            netplay_history: Default::default(),
         }
     }
 
@@ -68,6 +73,7 @@ impl Menu {
         audio.play_bgm("Menu");
 
         self.current_frame = 0;
+        self.netplay_history.clear();
         match resume_menu {
             ResumeMenu::NetplayDisconnect { reason: message } => {
                 self.state = MenuState::NetplayWait { message };
@@ -102,9 +108,18 @@ impl Menu {
 
         if (player_inputs.iter().any(|x| x.a.press || x.start.press)) && package.stages.len() > 0 {
             match ticker.cursor {
-                0 => self.state = MenuState::character_select(),
+                0 => {
+                    self.training = false;
+                    self.state = MenuState::character_select();
+                }
                 1 => {
+                    self.training = true;
+                    self.state = MenuState::character_select();
+                }
+                2 => {
+                    self.training = false;
                     netplay.connect_match_making(
+                        config.netplay_matchmaking_host.clone(),
                         config.netplay_region.clone().unwrap_or_else(|| "AU".into()), // TODO: set region screen if region.is_none()
                         2,
                     );
@@ -112,7 +127,7 @@ impl Menu {
                         message: String::from(""),
                     };
                 }
-                2 => {
+                3 => {
                     self.state = MenuState::replay_select();
                 }
                 _ => unreachable!(),
@@ -120,7 +135,24 @@ impl Menu {
         }
     }
 
-    pub fn step_replay_select(&mut self, player_inputs: &[PlayerInput]) {
+    pub fn step_replay_select(&mut self, package: &Package, player_inputs: &[PlayerInput]) {
+        if let &mut MenuState::ReplaySelect(ref mut replays, ref ticker) = &mut self.state {
+            if player_inputs.iter().any(|x| x.y.press) && !replays.is_empty() {
+                let replay = &mut replays[ticker.cursor];
+                match replays_files::set_replay_favourite(&replay.name, !replay.favourite)
+                    .and_then(|_| replays_files::load_replay_metadata(&replay.name))
+                {
+                    Ok(metadata) => {
+                        replay.favourite = metadata.favourite;
+                        replay.description = metadata.description();
+                    }
+                    Err(error) => {
+                        println!("Failed to favourite replay: {}\n{}", replay.name, error);
+                    }
+                }
+            }
+        }
+
         let back = if let &mut MenuState::ReplaySelect(ref replays, ref mut ticker) =
             &mut self.state
         {
@@ -136,10 +168,12 @@ impl Menu {
             }
 
             if (player_inputs.iter().any(|x| x.start.press || x.a.press)) && !replays.is_empty() {
-                let name = &replays[ticker.cursor];
-                match replays::load_replay(&format!("{}.zip", name)) {
+                let name = &replays[ticker.cursor].name;
+                match replays::load_replay(name)
+                    .and_then(|replay| replay.check_compatible(package).map(|_| replay))
+                {
                     Ok(replay) => {
-                        self.game_setup = Some(replay.into_game_setup(false));
+                        self.game_setup = Some(replay.into_game_setup());
                     }
                     Err(error) => {
                         println!("Failed to load replay: {}\n{}", name, error);
@@ -387,7 +421,7 @@ impl Menu {
                                 } else {
                                     match ticker.cursor - fighters.len() {
                                         0 => selection.ui = PlayerSelectUi::cpu_team(),
-                                        1 => { /* TODO: selection.ui = PlayerSelectUi::cpu_ai()*/ }
+                                        1 => selection.ui = PlayerSelectUi::cpu_ai(),
                                         2 => {
                                             remove_cpu = Some(selection_i);
                                         }
@@ -423,7 +457,18 @@ impl Menu {
                                     }
                                 }
                             }
-                            PlayerSelectUi::CpuAi(_) => {}
+                            PlayerSelectUi::CpuAi(ticker) => {
+                                if ticker.cursor < ai::AI_PROFILES.len() {
+                                    selection.cpu_ai = Some(ticker.cursor);
+                                } else {
+                                    match ticker.cursor - ai::AI_PROFILES.len() {
+                                        0 => selection.ui = PlayerSelectUi::cpu_fighter(package),
+                                        _ => {
+                                            unreachable!()
+                                        }
+                                    }
+                                }
+                            }
                             PlayerSelectUi::HumanUnplugged => unreachable!(),
                         }
                     }
@@ -561,17 +606,14 @@ impl Menu {
 
             // add CPU players
             if selection.ui.is_cpu() {
-                if selection.fighter.is_some()
-                /* && selection.cpu.is_some() TODO */
-                {
+                if selection.fighter.is_some() {
                     let fighter = selection.fighter.unwrap();
                     players.push(PlayerSetup {
                         fighter: fighters[fighter].0.clone(),
                         team: selection.team,
                     });
                     controllers.push(i - ais_skipped);
-                    ais.push(0); // TODO: delete this
-                                 // ais.push(selection.cpu_ai.unwrap()); TODO: add this
+                    ais.push(selection.cpu_ai.unwrap_or(ai::DEFAULT_AI_PROFILE));
                 } else {
                     ais_skipped += 1;
                 }
@@ -593,7 +635,11 @@ impl Menu {
             input_history: vec![],
             entity_history: Default::default(),
             stage_history: vec![],
-            rules: Default::default(), // TODO: this will be configured by the user in the menu
+            rules: if self.training {
+                Rules::training()
+            } else {
+                Default::default() // TODO: this will be configured by the user in the menu
+            },
             debug: false,
             max_history_frames: None,
             current_frame: 0,
@@ -604,6 +650,7 @@ impl Menu {
             edit: Edit::Stage,
             hot_reload_entities: None,
             hot_reload_stage: None,
+            events: vec![],
             init_seed,
             controllers,
             ais,
@@ -631,7 +678,7 @@ impl Menu {
             if !*replay_saved
                 && (config.auto_save_replay || player_inputs.iter().any(|x| x.l.press && x.r.press))
             {
-                replays::save_replay(&self.game_results.as_ref().unwrap().replay);
+                replays::save_replay(&self.game_results.as_ref().unwrap().replay, config);
                 *replay_saved = true;
             }
         }
@@ -678,6 +725,15 @@ impl Menu {
                     self.state = MenuState::GameSelect;
                 }
             }
+            NetplayState::SpectatorWait => {
+                self.state = MenuState::NetplayWait {
+                    message: format!("Waiting for game to spectate {}", load_character),
+                };
+                if player_inputs.iter().any(|x| x.b.press) {
+                    netplay.set_offline();
+                    self.state = MenuState::GameSelect;
+                }
+            }
             NetplayState::Disconnected { .. } => {
                 if player_inputs.iter().any(|x| x.a.press || x.b.press) {
                     netplay.set_offline();
@@ -707,12 +763,11 @@ impl Menu {
         if !netplay.skip_frame() {
             self.current_frame += 1;
 
-            let start = self.current_frame - netplay.frames_to_step();
+            let start = self.current_frame.saturating_sub(netplay.frames_to_step());
             let end = self.current_frame;
 
-            self.netplay_history.truncate(start);
-            if start > 0 {
-                let history = self.netplay_history.get(start - 1).unwrap();
+            // Only need to restore state when rolling back to a mispredicted frame
+            if let Some(history) = self.netplay_history.get(start) {
                 self.state = history.state.clone();
                 self.prev_state = history.prev_state.clone();
                 self.fighter_selections = history.fighter_selections.clone();
@@ -722,6 +777,16 @@ impl Menu {
             input.netplay_update();
 
             for frame in start..end {
+                self.netplay_history.push(
+                    frame,
+                    NetplayHistory {
+                        state: self.state.clone(),
+                        prev_state: self.prev_state.clone(),
+                        fighter_selections: self.fighter_selections.clone(),
+                        stage_ticker: self.stage_ticker.clone(),
+                    },
+                );
+
                 if let NetplayState::Disconnected { reason } = netplay.state() {
                     self.state = MenuState::NetplayWait { message: reason };
                 }
@@ -734,7 +799,9 @@ impl Menu {
                         MenuState::GameSelect => {
                             self.step_game_select(package, config, &player_inputs, netplay)
                         }
-                        MenuState::ReplaySelect(_, _) => self.step_replay_select(&player_inputs),
+                        MenuState::ReplaySelect(_, _) => {
+                            self.step_replay_select(package, &player_inputs)
+                        }
                         MenuState::CharacterSelect { .. } => {
                             self.step_fighter_select(package, &player_inputs, netplay)
                         }
@@ -747,13 +814,6 @@ impl Menu {
                         }
                     };
                 }
-
-                self.netplay_history.push(NetplayHistory {
-                    state: self.state.clone(),
-                    prev_state: self.prev_state.clone(),
-                    fighter_selections: self.fighter_selections.clone(),
-                    stage_ticker: self.stage_ticker.clone(),
-                });
             }
         }
 
@@ -777,7 +837,8 @@ impl Menu {
                     )
                 }
                 MenuState::ReplaySelect(ref replays, ref ticker) => {
-                    RenderMenuState::ReplaySelect(replays.clone(), ticker.cursor)
+                    let descriptions = replays.iter().map(|x| x.description.clone()).collect();
+                    RenderMenuState::ReplaySelect(descriptions, ticker.cursor)
                 }
                 MenuState::NetplayWait { ref message } => {
                     RenderMenuState::GenericText(message.clone())
@@ -815,7 +876,7 @@ impl Menu {
 #[derive(Clone)]
 pub enum MenuState {
     GameSelect,
-    ReplaySelect(Vec<String>, MenuTicker), // MenuTicker must be tied with the Vec<String>, otherwise they may become out of sync
+    ReplaySelect(Vec<ReplayListing>, MenuTicker), // MenuTicker must be tied with the Vec<ReplayListing>, otherwise they may become out of sync
     CharacterSelect { back_counter: usize },
     StageSelect,
     GameResults { replay_saved: bool },
@@ -824,7 +885,7 @@ pub enum MenuState {
 
 impl MenuState {
     pub fn replay_select() -> MenuState {
-        let replays = replays_files::get_replay_names();
+        let replays = replays_files::list_replays();
         let ticker = MenuTicker::new(replays.len());
         MenuState::ReplaySelect(replays, ticker)
     }
@@ -870,7 +931,6 @@ impl PlayerSelect {
 }
 
 #[derive(Clone)]
-#[allow(dead_code)]
 pub enum PlayerSelectUi {
     CpuAi(MenuTicker),
     CpuFighter(MenuTicker),
@@ -881,9 +941,8 @@ pub enum PlayerSelectUi {
 }
 
 impl PlayerSelectUi {
-    #[allow(dead_code)]
     pub fn cpu_ai() -> Self {
-        PlayerSelectUi::CpuAi(MenuTicker::new(/* TODO: number_of_ai + */ 1))
+        PlayerSelectUi::CpuAi(MenuTicker::new(ai::AI_PROFILES.len() + 1))
     }
 
     pub fn cpu_fighter(package: &Package) -> Self {
@@ -0,0 +1,85 @@
+use crate::entity::fighters::player::Player;
+use crate::entity::Entities;
+
+use canon_collision_lib::entity_def::player::PlayerAction;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Something notable that happened to a player, used to jump between the interesting parts of a replay
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Node)]
+pub struct ReplayEvent {
+    /// The first frame the event is visible on
+    pub frame: usize,
+    /// The index of the player the event happened to
+    pub player: usize,
+    pub ty: ReplayEventType,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Node)]
+pub enum ReplayEventType {
+    Death,
+    StockLoss,
+    Hit,
+    LedgeGrab,
+    Taunt,
+}
+
+impl Default for ReplayEventType {
+    fn default() -> ReplayEventType {
+        ReplayEventType::Hit
+    }
+}
+
+/// Compares every players state before and after a frame was stepped to find the events that occured on that frame
+pub fn detect_events(frame: usize, before: &Entities, after: &Entities) -> Vec<ReplayEvent> {
+    let before: HashMap<usize, (&Player, &str)> = before
+        .values()
+        .filter_map(|x| {
+            x.ty.get_player()
+                .map(|player| (player.id, (player, x.state.action.as_ref())))
+        })
+        .collect();
+
+    let mut events = vec![];
+    for entity in after.values() {
+        let player = match entity.ty.get_player() {
+            Some(player) => player,
+            None => continue,
+        };
+        let (prev_player, prev_action) = match before.get(&player.id) {
+            Some(prev) => *prev,
+            None => continue,
+        };
+        let mut push = |ty| {
+            events.push(ReplayEvent {
+                frame,
+                player: player.id,
+                ty,
+            })
+        };
+
+        if player.result.deaths.len() > prev_player.result.deaths.len() {
+            push(ReplayEventType::Death);
+        }
+        if player.stocks < prev_player.stocks {
+            push(ReplayEventType::StockLoss);
+        }
+        if player.body.damage > prev_player.body.damage {
+            push(ReplayEventType::Hit);
+        }
+
+        let action = entity.state.action.as_ref();
+        if action != prev_action {
+            match PlayerAction::from_str(action) {
+                Ok(PlayerAction::LedgeGrab) => push(ReplayEventType::LedgeGrab),
+                Ok(PlayerAction::TauntUp)
+                | Ok(PlayerAction::TauntDown)
+                | Ok(PlayerAction::TauntLeft)
+                | Ok(PlayerAction::TauntRight) => push(ReplayEventType::Taunt),
+                _ => {}
+            }
+        }
+    }
+    events
+}
@@ -1,158 +1,364 @@
 use crate::camera::Camera;
-use crate::entity::{DebugEntities, Entities};
-use crate::game::{Edit, Game, GameSetup, GameState, PlayerSetup};
-use crate::rules::Rules;
+use crate::game::{Edit, Game, GameSetup, GameState};
+use crate::replay_events::ReplayEvent;
+use crate::rules::{Goal, Rules};
 
+use canon_collision_lib::config::Config;
 use canon_collision_lib::files;
 use canon_collision_lib::input::state::ControllerInput;
 use canon_collision_lib::input::Input;
-use canon_collision_lib::replays_files;
-use canon_collision_lib::stage::{DebugStage, Stage};
+use canon_collision_lib::package::Package;
+use canon_collision_lib::replays_files::{self, ReplayMetadata};
 
-use chrono::{DateTime, Local};
+use chrono::Local;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use std::fs::{DirBuilder, File};
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+// The replay file format is described in canon_collision_lib::replays_files
 
 pub fn load_replay(name: &str) -> Result<Replay, String> {
-    let replay_path = replays_files::get_replay_path(name);
-    files::load_struct_bincode(&replay_path)
+    read_replay(&replays_files::get_replay_path(name)?)
+}
+
+/// Saves the replay under a name generated from the configured template, then deletes the oldest replays that exceed the configured limits.
+pub fn save_replay(replay: &Replay, config: &Config) {
+    // Training settings change the game state without being recorded, so the replay would desync
+    if let Goal::Training = replay.rules.goal {
+        info!("Training games are not saved as replays");
+        return;
+    }
+    let name = replay
+        .metadata
+        .name_from_template(&config.replay_name_template);
+    let data = match encode_replay(replay) {
+        Ok(data) => data,
+        Err(err) => {
+            error!("Failed to encode replay '{}': {}", name, err);
+            return;
+        }
+    };
+    let (name, mut file) = match replays_files::create_unique_replay(&name) {
+        Ok(created) => created,
+        Err(err) => {
+            error!("{}", err);
+            return;
+        }
+    };
+    if let Err(err) = file.write_all(&data) {
+        error!("Failed to save replay '{}': {}", name, err);
+        return;
+    }
+
+    let max_bytes = config.replay_max_megabytes.map(|x| x * 1024 * 1024);
+    for deleted in replays_files::prune_replays(config.replay_max_count, max_bytes, &name) {
+        info!("Deleted old replay '{}'", deleted);
+    }
+}
+
+pub fn read_replay(path: &Path) -> Result<Replay, String> {
+    let file = File::open(path).map_err(|x| x.to_string())?;
+    let mut reader = BufReader::new(file);
+    let metadata = replays_files::read_metadata(&mut reader)?;
+    let body: ReplayBody = bincode::deserialize_from(DeflateDecoder::new(reader))
+        .map_err(|x| format!("The replay body is corrupted: {}", x))?;
+
+    Ok(Replay {
+        metadata,
+        init_seed: body.init_seed,
+        rules: body.rules,
+        selected_controllers: body.selected_controllers,
+        selected_ais: body.selected_ais,
+        input_history: decode_input_runs(body.input_runs),
+        state_hashes: body.state_hashes,
+        events: body.events,
+    })
+}
+
+pub fn write_replay(path: &Path, replay: &Replay) -> Result<(), String> {
+    let data = encode_replay(replay)?;
+    if let Some(parent) = path.parent() {
+        DirBuilder::new()
+            .recursive(true)
+            .create(parent)
+            .map_err(|x| x.to_string())?;
+    }
+    File::create(path)
+        .and_then(|mut file| file.write_all(&data))
+        .map_err(|x| x.to_string())
+}
+
+/// Returns the entire contents of a replay file
+fn encode_replay(replay: &Replay) -> Result<Vec<u8>, String> {
+    let body = ReplayBody {
+        init_seed: replay.init_seed,
+        rules: replay.rules.clone(),
+        selected_controllers: replay.selected_controllers.clone(),
+        selected_ais: replay.selected_ais.clone(),
+        input_runs: encode_input_runs(&replay.input_history),
+        state_hashes: replay.state_hashes.clone(),
+        events: replay.events.clone(),
+    };
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    bincode::serialize_into(&mut encoder, &body).unwrap();
+    let body = encoder.finish().map_err(|x| x.to_string())?;
+
+    let mut data = replays_files::encode_header(&replay.metadata);
+    data.extend_from_slice(&body);
+    Ok(data)
+}
+
+/// The part of the replay that is only needed to resimulate the game
+#[derive(Serialize, Deserialize)]
+struct ReplayBody {
+    init_seed: u64,
+    rules: Rules,
+    selected_controllers: Vec<usize>,
+    selected_ais: Vec<usize>,
+    input_runs: Vec<InputRun>,
+    state_hashes: Vec<u32>,
+    events: Vec<ReplayEvent>,
+}
+
+/// The inputs of consecutive frames that all have identical inputs
+#[derive(Serialize, Deserialize)]
+struct InputRun {
+    frames: u32,
+    /// structure: controllers Vec<ControllerInput>
+    inputs: Vec<ControllerInput>,
+}
+
+fn encode_input_runs(input_history: &[Vec<ControllerInput>]) -> Vec<InputRun> {
+    let mut runs: Vec<InputRun> = vec![];
+    for inputs in input_history {
+        match runs.last_mut() {
+            Some(run) if &run.inputs == inputs => run.frames += 1,
+            _ => runs.push(InputRun {
+                frames: 1,
+                inputs: inputs.clone(),
+            }),
+        }
+    }
+    runs
 }
 
-pub fn save_replay(replay: &Replay) {
-    let replay_path =
-        replays_files::get_replay_path(&format!("{}.zip", replay.timestamp.to_rfc2822())); // TODO: could still collide under strange circumstances: check and handle
-    files::save_struct_bincode(&replay_path, &replay)
+fn decode_input_runs(runs: Vec<InputRun>) -> Vec<Vec<ControllerInput>> {
+    let mut input_history = vec![];
+    for run in runs {
+        for _ in 0..run.frames {
+            input_history.push(run.inputs.clone());
+        }
+    }
+    input_history
 }
 
+/// Contains only what is needed to resimulate a game, the game state of every frame is reconstructed by stepping through the input_history.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Replay {
+    pub metadata: ReplayMetadata,
     pub init_seed: u64,
-    pub timestamp: DateTime<Local>,
-    pub input_history: Vec<Vec<ControllerInput>>,
-    pub entity_history: Vec<Entities>,
-    pub stage_history: Vec<Stage>,
+    pub rules: Rules,
     pub selected_controllers: Vec<usize>,
-    pub selected_players: Vec<PlayerSetup>,
     pub selected_ais: Vec<usize>,
-    pub selected_stage: String,
-    pub rules: Rules,
-    pub max_history_frames: Option<usize>,
-    pub deleted_history_frames: usize,
-    pub hot_reload_current_frame: usize,
-    pub hot_reload_camera: Camera,
-    pub hot_reload_debug_entities: DebugEntities,
-    pub hot_reload_debug_stage: DebugStage,
-    pub hot_reload_entities: Entities,
-    pub hot_reload_stage: Stage,
-    pub hot_reload_as_running: bool,
-    pub hot_reload_edit: Edit,
+    /// structure: frames Vec<controllers Vec<ControllerInput>>
+    pub input_history: Vec<Vec<ControllerInput>>,
+    /// The state hash of every frame as it was originally played, used to verify the replay resimulates identically.
+    /// Empty if the earliest frames were removed from the history before the replay was saved.
+    pub state_hashes: Vec<u32>,
+    /// Sorted by frame
+    pub events: Vec<ReplayEvent>,
 }
 
 impl Replay {
     pub fn new(game: &Game, input: &Input) -> Replay {
-        let mut selected_players = vec![];
-        for (_, entity) in &game.entities() {
-            if let Some(fighter) = entity.ty.get_player() {
-                selected_players.push(PlayerSetup {
-                    fighter: entity.state.entity_def_key.clone(),
-                    team: fighter.team,
-                });
-            }
-        }
-
-        let hot_reload_as_running = matches!(game.state, GameState::Local);
-
+        let input_history = input.get_history();
         Replay {
+            metadata: ReplayMetadata {
+                timestamp: Local::now(),
+                build_version: files::build_version(),
+                package_hash: game.package.compute_hash(),
+                stage: game.selected_stage.clone(),
+                players: game.selected_players(),
+                duration: input_history.len(),
+                winner: None,
+                favourite: false,
+            },
             init_seed: game.init_seed,
-            timestamp: Local::now(),
-            input_history: input.get_history(),
-            entity_history: game.entity_history(),
-            stage_history: game.stage_history.clone(),
+            rules: game.rules.clone(),
             selected_controllers: game.selected_controllers.clone(),
             selected_ais: game.selected_ais.clone(),
-            selected_stage: game.selected_stage.clone(),
-            rules: game.rules.clone(),
-            max_history_frames: game.max_history_frames,
-            deleted_history_frames: game.deleted_history_frames,
-            hot_reload_current_frame: game.current_frame,
-            hot_reload_camera: game.camera.clone(),
-            hot_reload_debug_entities: game.debug_entities(),
-            hot_reload_debug_stage: game.debug_stage.clone(),
-            hot_reload_entities: game.entities(),
-            hot_reload_stage: game.stage.clone(),
-            hot_reload_edit: game.edit(),
-            hot_reload_as_running,
-            selected_players,
+            input_history,
+            state_hashes: game.state_hashes(),
+            events: game.events.clone(),
         }
     }
 
-    // TODO: maybe hotreloading should be its own thing seperate to replays
-    // Its increasing looking like hot reloading wants to serialize EVERYTHING
-    // whereas replays only wants to serialize the bits relevant to gameplay
-    pub fn into_game_setup(self, hot_reload: bool) -> GameSetup {
-        let state = if !hot_reload {
-            GameState::ReplayForwardsFromHistory
-        } else if self.hot_reload_as_running {
-            GameState::Local
-        } else {
-            GameState::Paused
-        };
-
-        let current_frame = if hot_reload {
-            self.hot_reload_current_frame
-        } else {
-            self.deleted_history_frames
-        };
-
-        let debug_entities = if hot_reload {
-            Some(self.hot_reload_debug_entities)
-        } else {
-            None
-        };
-
-        let debug_stage = if hot_reload {
-            Some(self.hot_reload_debug_stage)
-        } else {
-            None
-        };
-
-        let camera = if hot_reload {
-            self.hot_reload_camera
-        } else {
-            Camera::new()
-        };
-
-        let hot_reload_entities = if hot_reload {
-            Some(self.hot_reload_entities)
-        } else {
-            None
-        };
-
-        let hot_reload_stage = if hot_reload {
-            Some(self.hot_reload_stage)
-        } else {
-            None
-        };
+    /// Returns an error describing why the replay would not resimulate correctly with the current build and package
+    pub fn check_compatible(&self, package: &Package) -> Result<(), String> {
+        let build_version = files::build_version();
+        if self.metadata.build_version != build_version {
+            return Err(format!(
+                "The replay was recorded with build '{}' but this is build '{}'",
+                self.metadata.build_version, build_version
+            ));
+        }
+        if self.metadata.package_hash != package.compute_hash() {
+            return Err(String::from(
+                "The replay was recorded with a package containing different gameplay data",
+            ));
+        }
+        Ok(())
+    }
 
+    pub fn into_game_setup(self) -> GameSetup {
         GameSetup {
             init_seed: self.init_seed,
             input_history: self.input_history,
-            entity_history: self.entity_history,
-            stage_history: self.stage_history,
+            entity_history: vec![],
+            stage_history: vec![],
             controllers: self.selected_controllers,
-            players: self.selected_players,
+            players: self.metadata.players,
             ais: self.selected_ais,
-            stage: self.selected_stage,
+            stage: self.metadata.stage,
             rules: self.rules,
-            max_history_frames: self.max_history_frames,
-            deleted_history_frames: self.deleted_history_frames,
-            edit: self.hot_reload_edit,
-            current_frame,
+            max_history_frames: None,
+            deleted_history_frames: 0,
+            edit: Edit::Stage,
+            current_frame: 0,
             debug: false,
-            camera,
-            debug_entities,
-            debug_stage,
-            hot_reload_entities,
-            hot_reload_stage,
-            state,
+            camera: Camera::new(),
+            debug_entities: None,
+            debug_stage: None,
+            hot_reload_entities: None,
+            hot_reload_stage: None,
+            events: self.events,
+            state: GameState::ReplayForwardsFromInput,
         }
     }
 }
+
+#[test]
+fn replay_round_trip() {
+    use crate::replay_events::ReplayEventType;
+    use canon_collision_lib::replays_files::PlayerSetup;
+
+    let replay = Replay {
+        metadata: ReplayMetadata {
+            timestamp: Local::now(),
+            build_version: String::from("test"),
+            package_hash: 42,
+            stage: String::from("Stage"),
+            players: vec![PlayerSetup {
+                fighter: String::from("Toriel"),
+                team: 1,
+            }],
+            duration: 3,
+            winner: Some(0),
+            favourite: false,
+        },
+        init_seed: 7,
+        rules: Rules::default(),
+        selected_controllers: vec![0],
+        selected_ais: vec![],
+        input_history: vec![
+            vec![ControllerInput::empty()],
+            vec![ControllerInput::empty()],
+            vec![ControllerInput {
+                plugged_in: true,
+                ..ControllerInput::empty()
+            }],
+        ],
+        state_hashes: vec![1, 2, 3, 4],
+        events: vec![ReplayEvent {
+            frame: 2,
+            player: 0,
+            ty: ReplayEventType::Taunt,
+        }],
+    };
+
+    let path = std::env::temp_dir().join("canon_collision_replay_round_trip.replay");
+    write_replay(&path, &replay).unwrap();
+    let loaded = read_replay(&path).unwrap();
+    assert_eq!(loaded.metadata.package_hash, 42);
+    assert_eq!(loaded.metadata.winner, Some(0));
+    assert_eq!(loaded.init_seed, 7);
+    assert!(loaded.input_history == replay.input_history);
+    assert_eq!(loaded.state_hashes, replay.state_hashes);
+    assert_eq!(loaded.events[0].ty, ReplayEventType::Taunt);
+
+    // a file that is not a replay gives a clear error
+    File::create(&path)
+        .unwrap()
+        .write_all(b"PK\x03\x04 not a replay")
+        .unwrap();
+    assert_eq!(
+        read_replay(&path).err(),
+        Some(String::from("The file is not a replay"))
+    );
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn offline_netplay_replay_verifies() {
+    use crate::simulate::{self, SimulationSetup};
+    use crate::verify::{self, RecordedStates};
+    use canon_collision_lib::replays_files::PlayerSetup;
+
+    let package = Package::find_package_in_parent_dirs()
+        .and_then(Package::open)
+        .unwrap();
+    let setup = SimulationSetup {
+        stage: String::from("BeatMesa"),
+        players: vec![
+            PlayerSetup {
+                fighter: String::from("Toriel"),
+                team: 0,
+            },
+            PlayerSetup {
+                fighter: String::from("Toriel"),
+                team: 1,
+            },
+        ],
+        cpu_players: vec![],
+        rules: Rules::default(),
+        seed: 3,
+        input_file: Default::default(),
+        max_frames: Some(120),
+    };
+    // both players move around so the replay only verifies if their inputs are saved
+    let inputs: Vec<Vec<ControllerInput>> = (0..120)
+        .map(|frame| {
+            vec![
+                ControllerInput {
+                    plugged_in: true,
+                    stick_x: if frame % 60 < 30 { 1.0 } else { -1.0 },
+                    a: frame % 20 == 0,
+                    ..ControllerInput::empty()
+                },
+                ControllerInput {
+                    plugged_in: true,
+                    stick_x: if frame % 40 < 20 { -1.0 } else { 1.0 },
+                    y: frame == 50,
+                    ..ControllerInput::empty()
+                },
+            ]
+        })
+        .collect();
+    let results = simulate::simulate(package.clone(), setup, &inputs).unwrap();
+
+    let path = std::env::temp_dir().join("canon_collision_offline_netplay_replay.replay");
+    write_replay(&path, &results.replay).unwrap();
+    let mut loaded = read_replay(&path).unwrap();
+    std::fs::remove_file(&path).ok();
+
+    let state_hashes = std::mem::take(&mut loaded.state_hashes);
+    assert!(!state_hashes.is_empty());
+    let recorded = RecordedStates::Hashes(&state_hashes);
+    if let Err(divergence) = verify::verify(package, loaded.into_game_setup(), recorded) {
+        panic!("{}", divergence.report());
+    }
+}
@@ -27,8 +27,11 @@ pub struct PlayerResult {
     pub team: usize,
     pub controller: usize,
     pub place: usize,
+    /// DeathRecord::player is the player that was killed
     pub kills: Vec<DeathRecord>,
     pub deaths: Vec<DeathRecord>,
+    /// Only used by Goal::KillDeathScore
+    pub score: Option<i64>,
     pub lcancel_percent: f32,
 }
 
@@ -48,3 +51,29 @@ pub struct DeathRecord {
     pub player: Option<usize>, // None indicates self-destruct
     pub frame: usize,
 }
+
+/// Self-destructs cost this many points on top of the point lost for dying
+const SELF_DESTRUCT_PENALTY: i64 = 1;
+
+/// Every kill is worth a point and every death loses a point
+pub fn kill_death_score(kills: &[DeathRecord], deaths: &[DeathRecord]) -> i64 {
+    let self_destructs = deaths.iter().filter(|x| x.player.is_none()).count() as i64;
+    kills.len() as i64 - deaths.len() as i64 - self_destructs * SELF_DESTRUCT_PENALTY
+}
+
+#[test]
+fn kill_death_score_penalizes_self_destructs() {
+    let kill = DeathRecord {
+        player: Some(1),
+        frame: 0,
+    };
+    let self_destruct = DeathRecord {
+        player: None,
+        frame: 0,
+    };
+    assert_eq!(
+        kill_death_score(&[kill.clone(), kill.clone()], &[kill.clone()]),
+        1
+    );
+    assert_eq!(kill_death_score(&[kill], &[self_destruct]), -1);
+}
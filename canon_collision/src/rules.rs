@@ -27,6 +27,16 @@ impl Default for Rules {
 }
 
 impl Rules {
+    /// Infinite stocks and no timer, the game only ends when quit from the pause menu
+    pub fn training() -> Rules {
+        Rules {
+            goal: Goal::Training,
+            stock_count: None,
+            time_limit_seconds: None,
+            ..Default::default()
+        }
+    }
+
     pub fn time_limit_frames(&self) -> Option<u64> {
         self.time_limit_seconds.map(|x| x * 60)
     }
@@ -36,6 +46,8 @@ impl Rules {
 pub enum Goal {
     KillDeathScore,
     LastManStanding,
+    /// Players respawn forever and can set their damage, save snapshots and view combo statistics
+    Training,
 }
 
 #[derive(Clone, Serialize, Deserialize, Node)]
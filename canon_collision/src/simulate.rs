@@ -0,0 +1,246 @@
+use crate::ai;
+use crate::audio::Audio;
+use crate::camera::Camera;
+use crate::game::{Edit, Game, GameSetup, GameState, PlayerSetup};
+use crate::menu::ResumeMenu;
+use crate::replays;
+use crate::results::GameResults;
+use crate::rules::Rules;
+use canon_collision_lib::config::Config;
+use canon_collision_lib::files;
+use canon_collision_lib::input::state::ControllerInput;
+use canon_collision_lib::input::Input;
+use canon_collision_lib::network::{ChannelNetwork, Netplay};
+use canon_collision_lib::package::Package;
+use canon_collision_lib::replays_files::REPLAY_EXTENSION;
+
+use std::path::{Path, PathBuf};
+
+use winit_input_helper::WinitInputHelper;
+
+/// Describes a match to simulate, loaded from a json file.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SimulationSetup {
+    pub stage: String,
+    /// Players controlled by the input file, player n uses controller n
+    pub players: Vec<PlayerSetup>,
+    /// Players controlled by the default AI profile, using the controllers after the input file controllers
+    #[serde(default)]
+    pub cpu_players: Vec<PlayerSetup>,
+    #[serde(default)]
+    pub rules: Rules,
+    pub seed: u64,
+    /// A json file containing the controller inputs of every frame, relative to the setup file.
+    /// Frames after the end of the file and missing controllers are given neutral inputs.
+    /// structure: frames Vec<controllers Vec<ControllerInput>>
+    pub input_file: PathBuf,
+    /// The match is ended after this many frames even if the rules have not ended it
+    pub max_frames: Option<usize>,
+}
+
+impl SimulationSetup {
+    fn into_game_setup(self) -> GameSetup {
+        let human_players = self.players.len();
+        let controllers = (0..human_players + self.cpu_players.len()).collect();
+        let ais = self
+            .cpu_players
+            .iter()
+            .map(|_| ai::DEFAULT_AI_PROFILE)
+            .collect();
+        let mut players = self.players;
+        players.extend(self.cpu_players);
+
+        GameSetup {
+            init_seed: self.seed,
+            input_history: vec![],
+            entity_history: vec![],
+            stage_history: vec![],
+            stage: self.stage,
+            state: GameState::Local,
+            debug: false,
+            max_history_frames: None,
+            current_frame: 0,
+            deleted_history_frames: 0,
+            debug_entities: Default::default(),
+            debug_stage: Default::default(),
+            camera: Camera::new(),
+            edit: Edit::Stage,
+            hot_reload_entities: None,
+            hot_reload_stage: None,
+            events: vec![],
+            rules: self.rules,
+            controllers,
+            players,
+            ais,
+        }
+    }
+}
+
+/// Simulate the match described by the setup file as fast as possible, without graphics, audio or controllers.
+/// The replay and results of the match are written to the output directory.
+pub fn run(setup_path: &Path, output_dir: &Path) -> Result<(), String> {
+    let setup: SimulationSetup = files::load_struct_json(setup_path)
+        .map_err(|err| format!("Failed to load simulation setup {:?}: {}", setup_path, err))?;
+    let input_path = setup_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(&setup.input_file);
+    let inputs: Vec<Vec<ControllerInput>> = files::load_struct_json(&input_path)
+        .map_err(|err| format!("Failed to load simulation inputs {:?}: {}", input_path, err))?;
+
+    let package = Package::find_package_in_parent_dirs()
+        .and_then(Package::open)
+        .ok_or("Could not load package/ in current directory or any of its parent directories.")?;
+
+    let cpu_players = setup.cpu_players.len();
+    let results = simulate(package, setup, &inputs)?;
+
+    let replay_path = output_dir.join(format!("match.{}", REPLAY_EXTENSION));
+    replays::write_replay(&replay_path, &results.replay)?;
+    let results_path = output_dir.join("results.json");
+    files::save_struct_json(&results_path, &results.player_results);
+    println!(
+        "Simulated {} frames, wrote {:?} and {:?}",
+        results.replay.input_history.len(),
+        replay_path,
+        results_path
+    );
+    if cpu_players > 0 {
+        println!(
+            "{} CPU players used the {} AI",
+            cpu_players,
+            ai::AI_PROFILES[ai::DEFAULT_AI_PROFILE].name
+        );
+    }
+    Ok(())
+}
+
+/// A Netplay that is never connected, for stepping a Game without a network
+pub fn offline_netplay() -> Netplay {
+    // An offline Netplay never sends or receives packets, so the transport is never used
+    let network = ChannelNetwork::new(0, 0, 0.0, 0);
+    Netplay::new(Box::new(ChannelNetwork::transport(
+        &network,
+        "127.0.0.1:0".parse().unwrap(),
+    )))
+}
+
+/// Steps a Game through the input history of its setup, without graphics, audio or controllers
+pub struct Replayer {
+    game: Game,
+    input: Input,
+    config: Config,
+    audio: Audio,
+    os_input: WinitInputHelper,
+    netplay: Netplay,
+    has_inputs: bool,
+}
+
+impl Replayer {
+    pub fn new(package: Package, mut setup: GameSetup) -> Replayer {
+        let mut audio = Audio::muted();
+        let mut input = Input::headless();
+        let has_inputs = !setup.input_history.is_empty();
+        input.set_history(std::mem::take(&mut setup.input_history));
+        setup.state = GameState::ReplayForwardsFromInput;
+        let game = Game::new(package, setup, &mut audio);
+
+        Replayer {
+            game,
+            input,
+            config: Config::default(),
+            audio,
+            os_input: WinitInputHelper::new(),
+            netplay: offline_netplay(),
+            has_inputs,
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Step to the next frame, returns false if there are no inputs left to step with
+    pub fn step(&mut self) -> bool {
+        if !self.has_inputs {
+            return false;
+        }
+        let frame = self.game.current_frame;
+        self.game.step(
+            &mut self.config,
+            &mut self.input,
+            &self.os_input,
+            true,
+            &mut self.netplay,
+            &mut self.audio,
+        );
+        self.game.current_frame != frame
+    }
+}
+
+/// Step the match until the rules end it or max_frames is reached.
+pub fn simulate(
+    package: Package,
+    setup: SimulationSetup,
+    inputs: &[Vec<ControllerInput>],
+) -> Result<GameResults, String> {
+    if !package.stages.contains_key(&setup.stage) {
+        return Err(format!(
+            "Package does not contain selected stage '{}'",
+            setup.stage
+        ));
+    }
+    for player in setup.players.iter().chain(setup.cpu_players.iter()) {
+        if !package.entities.contains_key(&player.fighter) {
+            return Err(format!(
+                "Package does not contain selected fighter '{}'",
+                player.fighter
+            ));
+        }
+    }
+
+    let human_players = setup.players.len();
+    let max_frames = setup.max_frames;
+    let mut config = Config::default();
+    let mut audio = Audio::muted();
+    let mut input = Input::headless();
+    let os_input = WinitInputHelper::new();
+    let mut netplay = offline_netplay();
+    let mut game = Game::new(package, setup.into_game_setup(), &mut audio);
+
+    loop {
+        if max_frames.map_or(false, |max| game.current_frame >= max) {
+            match game.generate_game_results(&input) {
+                GameState::Quit(ResumeMenu::Results(results)) => return Ok(results),
+                _ => unreachable!(),
+            }
+        }
+
+        // The next frame to be stepped is current_frame + 1 which uses inputs index current_frame
+        let mut frame_inputs = inputs.get(game.current_frame).cloned().unwrap_or_default();
+        frame_inputs.truncate(human_players);
+        while frame_inputs.len() < human_players {
+            frame_inputs.push(ControllerInput {
+                plugged_in: true,
+                ..ControllerInput::empty()
+            });
+        }
+        let ai_inputs = ai::gen_inputs(&game);
+        input.step_headless(frame_inputs, &ai_inputs);
+
+        match game.step(
+            &mut config,
+            &mut input,
+            &os_input,
+            true,
+            &mut netplay,
+            &mut audio,
+        ) {
+            GameState::Quit(ResumeMenu::Results(results)) => return Ok(results),
+            GameState::Quit(_) => return Err(String::from("The match was quit before it ended")),
+            // Pausing only makes sense when someone is watching
+            GameState::Paused => game.state = GameState::Local,
+            _ => {}
+        }
+    }
+}
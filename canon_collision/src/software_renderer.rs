@@ -0,0 +1,422 @@
+use crate::entity::{RenderEntity, RenderEntityFrame};
+use crate::game::{RenderGame, RenderObject, RenderRect, RenderSpawnPoint};
+use crate::graphics;
+use crate::replays;
+use crate::simulate::Replayer;
+use canon_collision_lib::entity_def::CollisionBox;
+use canon_collision_lib::geometry::Rect;
+use canon_collision_lib::package::Package;
+use canon_collision_lib::stage::Surface;
+
+use std::fs::{DirBuilder, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+/// The resolution of exported frames
+pub const EXPORT_WIDTH: usize = 960;
+pub const EXPORT_HEIGHT: usize = 540;
+
+const BACKGROUND_COLOR: [f32; 4] = [0.05, 0.05, 0.1, 1.0];
+
+/// Rasterizes the debug view of a RenderGame (stage surfaces, colboxes, ECBs, spawn points and debug rects) on the CPU.
+/// The fighter and stage models are not drawn.
+pub struct SoftwareRenderer {
+    width: usize,
+    height: usize,
+    /// structure: rows Vec<columns Vec<RGBA u8>> flattened, the first row is the top of the image
+    pixels: Vec<u8>,
+    /// The area of the game visible in the image
+    view: Rect,
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: usize, height: usize) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![0; width * height * 4],
+            view: Rect {
+                x1: -1.0,
+                y1: -1.0,
+                x2: 1.0,
+                y2: 1.0,
+            },
+        }
+    }
+
+    pub fn render(&mut self, render: &RenderGame) {
+        self.set_view(&render.camera.rect);
+        self.clear(BACKGROUND_COLOR);
+
+        if render.render_stage_mode.debug() {
+            for surface in &render.surfaces {
+                self.draw_surface(surface);
+            }
+        }
+
+        for object in &render.entities {
+            match object {
+                RenderObject::Entity(entity) => self.draw_entity(entity),
+                RenderObject::RectOutline(rect) => self.draw_rect_outline(rect),
+                RenderObject::SpawnPoint(point) => self.draw_spawn_point(point),
+            }
+        }
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path).map_err(|x| x.to_string())?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|x| x.to_string())?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|x| x.to_string())
+    }
+
+    /// Grow the camera rect to match the aspect ratio of the image, so the game is not stretched
+    fn set_view(&mut self, camera: &Rect) {
+        let mut view = Rect {
+            x1: camera.left(),
+            y1: camera.bot(),
+            x2: camera.right(),
+            y2: camera.top(),
+        };
+        let aspect_ratio = self.width as f32 / self.height as f32;
+        let width = view.x2 - view.x1;
+        let height = view.y2 - view.y1;
+        if width / height > aspect_ratio {
+            let middle = (view.y1 + view.y2) / 2.0;
+            view.y1 = middle - width / aspect_ratio / 2.0;
+            view.y2 = middle + width / aspect_ratio / 2.0;
+        } else {
+            let middle = (view.x1 + view.x2) / 2.0;
+            view.x1 = middle - height * aspect_ratio / 2.0;
+            view.x2 = middle + height * aspect_ratio / 2.0;
+        }
+        self.view = view;
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        let color = to_rgba8(color);
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    /// Convert a point in game space to a point in pixel space
+    fn to_pixel(&self, point: (f32, f32)) -> (f32, f32) {
+        let x = (point.0 - self.view.x1) / (self.view.x2 - self.view.x1) * self.width as f32;
+        let y = (self.view.y2 - point.1) / (self.view.y2 - self.view.y1) * self.height as f32;
+        (x, y)
+    }
+
+    /// The number of pixels covered by one unit of game space
+    fn pixels_per_unit(&self) -> f32 {
+        self.width as f32 / (self.view.x2 - self.view.x1)
+    }
+
+    fn draw_surface(&mut self, surface: &Surface) {
+        // Same colors as the wgpu renderer
+        let r = if surface.is_pass_through() {
+            0.4
+        } else if surface.floor.is_some() {
+            0.6
+        } else {
+            0.0
+        };
+        let g = if surface.ceiling { 0.5 } else { 0.0 };
+        let b = if surface.wall { 0.5 } else { 0.0 };
+        let color = [1.0 - g - b, 1.0 - r - b, 1.0 - r - g, 1.0];
+
+        // Surfaces are always at least one pixel thick
+        let thickness = (0.25 * self.pixels_per_unit()).max(0.5) / self.pixels_per_unit();
+        let angle = surface.render_angle() - 90f32.to_radians();
+        let d_x = angle.cos() * thickness;
+        let d_y = angle.sin() * thickness;
+        let a = (surface.x1 + d_x, surface.y1 + d_y);
+        let b = (surface.x2 + d_x, surface.y2 + d_y);
+        let c = (surface.x2 - d_x, surface.y2 - d_y);
+        let d = (surface.x1 - d_x, surface.y1 - d_y);
+        self.fill_triangle(a, b, c, color);
+        self.fill_triangle(a, c, d, color);
+    }
+
+    fn draw_entity(&mut self, entity: &RenderEntity) {
+        let frame = &entity.frames[0];
+
+        if entity.debug.render.debug() {
+            let edge_color = if entity.entity_selected {
+                [0.0, 1.0, 0.0, 1.0]
+            } else {
+                let c = entity.fighter_color;
+                [c[0], c[1], c[2], 1.0]
+            };
+            for colbox in &entity.frame_data.colboxes {
+                self.draw_colbox(frame, colbox, edge_color);
+            }
+        }
+
+        if entity.debug.ecb {
+            if let Some(ecb) = &frame.ecb {
+                let color = [1.0, 1.0, 1.0, 1.0];
+                let flip = if frame.face_right { 1.0 } else { -1.0 };
+                let (x, y) = frame.frame_bps;
+                let mid_y = (ecb.top + ecb.bottom) / 2.0;
+                let bottom = (x, y + ecb.bottom);
+                let left = (x + ecb.left * flip, y + mid_y);
+                let right = (x + ecb.right * flip, y + mid_y);
+                let top = (x, y + ecb.top);
+                self.fill_triangle(left, right, bottom, color);
+                self.fill_triangle(left, right, top, color);
+
+                // bps
+                self.fill_quad((x - 4.0, y - 0.15), (x + 4.0, y + 0.15), color);
+                self.fill_quad((x - 0.15, y - 4.0), (x + 0.15, y + 4.0), color);
+            }
+        }
+    }
+
+    fn draw_colbox(
+        &mut self,
+        frame: &RenderEntityFrame,
+        colbox: &CollisionBox,
+        edge_color: [f32; 4],
+    ) {
+        // Apply the same transformation as the wgpu renderers entity_matrix
+        let flip = if frame.face_right { 1.0 } else { -1.0 };
+        let x = colbox.point.0 * flip;
+        let y = colbox.point.1;
+        let (sin, cos) = frame.frame_angle.sin_cos();
+        let center = (
+            frame.frame_bps.0 + x * cos - y * sin,
+            frame.frame_bps.1 + x * sin + y * cos,
+        );
+
+        // Same colors as hitbox.wgsl
+        let fill_color = match graphics::get_render_id(&colbox.role) {
+            1 => [0.9, 0.9, 0.9, 1.0],
+            2 => [1.0, 0.0, 0.0, 1.0],
+            3 => [0.76, 0.106, 0.843, 1.0],
+            6 => [0.0, 0.64, 0.0, 1.0],
+            7 => [0.8, 0.8, 0.8, 1.0],
+            _ => [0.0, 0.0, 1.0, 1.0],
+        };
+        let edge_color = if graphics::get_render_id(&colbox.role) == 1 {
+            edge_color
+        } else {
+            fill_color
+        };
+        self.fill_circle(center, colbox.radius, fill_color, edge_color);
+    }
+
+    fn draw_rect_outline(&mut self, render_rect: &RenderRect) {
+        // Outlines are always at least one pixel thick
+        let width = 0.5f32.max(1.0 / self.pixels_per_unit());
+        let rect = &render_rect.rect;
+        let (left, right, bot, top) = (rect.left(), rect.right(), rect.bot(), rect.top());
+        let color = render_rect.color;
+        self.fill_quad((left, bot), (right, bot + width), color);
+        self.fill_quad((left, top - width), (right, top), color);
+        self.fill_quad((left, bot), (left + width, top), color);
+        self.fill_quad((right - width, bot), (right, top), color);
+    }
+
+    fn draw_spawn_point(&mut self, point: &RenderSpawnPoint) {
+        let flip = if point.face_right { 1.0 } else { -1.0 };
+        let (x, y) = (point.x, point.y);
+        let color = point.color;
+        self.fill_quad((x - 0.15, y - 4.0), (x + 0.15, y + 4.0), color);
+        self.fill_quad((x - 4.0, y - 0.15), (x + 4.0, y + 0.15), color);
+        self.fill_triangle(
+            (x + 4.2 * flip, y),
+            (x + 3.0 * flip, y - 1.0),
+            (x + 3.0 * flip, y + 1.0),
+            color,
+        );
+    }
+
+    /// Fill the axis aligned rectangle between the two corners
+    fn fill_quad(&mut self, a: (f32, f32), b: (f32, f32), color: [f32; 4]) {
+        self.fill_triangle(a, (b.0, a.1), b, color);
+        self.fill_triangle(a, b, (a.0, b.1), color);
+    }
+
+    fn fill_triangle(&mut self, a: (f32, f32), b: (f32, f32), c: (f32, f32), color: [f32; 4]) {
+        let a = self.to_pixel(a);
+        let b = self.to_pixel(b);
+        let c = self.to_pixel(c);
+
+        let area = edge(a, b, c);
+        if area == 0.0 {
+            return;
+        }
+
+        let (x_min, x_max) =
+            self.pixel_range(a.0.min(b.0).min(c.0), a.0.max(b.0).max(c.0), self.width);
+        let (y_min, y_max) =
+            self.pixel_range(a.1.min(b.1).min(c.1), a.1.max(b.1).max(c.1), self.height);
+        for y in y_min..y_max {
+            for x in x_min..x_max {
+                // Sample the center of the pixel
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(b, c, p) / area;
+                let w1 = edge(c, a, p) / area;
+                let w2 = edge(a, b, p) / area;
+                if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                    self.blend_pixel(x, y, color);
+                }
+            }
+        }
+    }
+
+    /// Fill a circle, pixels in the outer 20% of the radius use the edge color like hitbox.wgsl
+    fn fill_circle(
+        &mut self,
+        center: (f32, f32),
+        radius: f32,
+        color: [f32; 4],
+        edge_color: [f32; 4],
+    ) {
+        let center = self.to_pixel(center);
+        let radius = radius * self.pixels_per_unit();
+
+        let (x_min, x_max) = self.pixel_range(center.0 - radius, center.0 + radius, self.width);
+        let (y_min, y_max) = self.pixel_range(center.1 - radius, center.1 + radius, self.height);
+        for y in y_min..y_max {
+            for x in x_min..x_max {
+                let d_x = x as f32 + 0.5 - center.0;
+                let d_y = y as f32 + 0.5 - center.1;
+                let distance = (d_x * d_x + d_y * d_y).sqrt();
+                if distance <= radius {
+                    if distance > radius * 0.8 {
+                        self.blend_pixel(x, y, edge_color);
+                    } else {
+                        self.blend_pixel(x, y, color);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Clamp a range of pixel coordinates to the image
+    fn pixel_range(&self, min: f32, max: f32, size: usize) -> (usize, usize) {
+        let min = min.floor().max(0.0) as usize;
+        let max = (max.ceil().max(0.0) as usize).min(size);
+        (min.min(size), max)
+    }
+
+    fn blend_pixel(&mut self, x: usize, y: usize, color: [f32; 4]) {
+        let i = (y * self.width + x) * 4;
+        let alpha = color[3];
+        let pixel = &mut self.pixels[i..i + 4];
+        for (dest, value) in pixel.iter_mut().zip(color.iter()).take(3) {
+            let blended = value * alpha + *dest as f32 / 255.0 * (1.0 - alpha);
+            *dest = (blended.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        pixel[3] = 255;
+    }
+}
+
+/// Returns twice the signed area of the triangle abc, used to test which side of the edge ab the point c is on
+fn edge(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn to_rgba8(color: [f32; 4]) -> [u8; 4] {
+    [
+        (color[0].clamp(0.0, 1.0) * 255.0).round() as u8,
+        (color[1].clamp(0.0, 1.0) * 255.0).round() as u8,
+        (color[2].clamp(0.0, 1.0) * 255.0).round() as u8,
+        (color[3].clamp(0.0, 1.0) * 255.0).round() as u8,
+    ]
+}
+
+/// Resimulate the replay in the replays folder and write the debug view of every frame to the output directory as numbered PNG files.
+pub fn export_replay(name: &str, output_dir: &Path) -> Result<(), String> {
+    let package = Package::find_package_in_parent_dirs()
+        .and_then(Package::open)
+        .ok_or("Could not load package/ in current directory or any of its parent directories.")?;
+    let replay = replays::load_replay(name)?;
+    replay.check_compatible(&package)?;
+
+    DirBuilder::new()
+        .recursive(true)
+        .create(output_dir)
+        .map_err(|x| x.to_string())?;
+
+    let mut setup = replay.into_game_setup();
+    // Turns on every debug view of the stage and entities
+    setup.debug = true;
+    let mut replayer = Replayer::new(package, setup);
+    let mut renderer = SoftwareRenderer::new(EXPORT_WIDTH, EXPORT_HEIGHT);
+    let mut frames = 0;
+    loop {
+        renderer.render(&replayer.game().render());
+        let path = output_dir.join(format!("frame_{:06}.png", replayer.game().current_frame));
+        renderer.save_png(&path)?;
+        frames += 1;
+
+        if !replayer.step() {
+            break;
+        }
+    }
+
+    println!("Exported {} frames to {:?}", frames, output_dir);
+    Ok(())
+}
+
+#[test]
+fn software_renderer_draws_surfaces() {
+    use crate::camera::Camera;
+    use crate::game::GameState;
+    use canon_collision_lib::stage::RenderStageMode;
+
+    let mut camera = Camera::new();
+    camera.rect = Rect {
+        x1: -10.0,
+        y1: -10.0,
+        x2: 10.0,
+        y2: 10.0,
+    };
+    let render = RenderGame {
+        seed: [0; 32],
+        current_frame: 0,
+        surfaces: vec![Surface::new(-5.0, 0.0, 5.0, 0.0, true, false, false)],
+        selected_surfaces: Default::default(),
+        render_stage_mode: RenderStageMode::Debug,
+        stage_model_name: String::new(),
+        entities: vec![RenderObject::rect_outline(
+            Rect {
+                x1: -9.0,
+                y1: -9.0,
+                x2: 9.0,
+                y2: 9.0,
+            },
+            1.0,
+            0.0,
+            0.0,
+        )],
+        state: GameState::Local,
+        camera,
+        debug_lines: vec![],
+        timer: None,
+        bgm_metadata: None,
+    };
+
+    let mut renderer = SoftwareRenderer::new(100, 100);
+    renderer.render(&render);
+    let pixel = |x: usize, y: usize| {
+        let i = (y * 100 + x) * 4;
+        [
+            renderer.pixels[i],
+            renderer.pixels[i + 1],
+            renderer.pixels[i + 2],
+            renderer.pixels[i + 3],
+        ]
+    };
+    assert_eq!(pixel(50, 50), to_rgba8([1.0, 0.6, 0.6, 1.0]));
+    assert_eq!(pixel(50, 25), to_rgba8(BACKGROUND_COLOR));
+    assert_eq!(pixel(50, 5), to_rgba8([1.0, 0.0, 0.0, 1.0]));
+}
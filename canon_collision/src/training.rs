@@ -0,0 +1,166 @@
+use crate::entity::fighters::player::Player;
+use crate::entity::Entities;
+
+use canon_collision_lib::entity_def::player::PlayerAction;
+
+use treeflection::{Node, NodeRunner, NodeToken};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Settings and statistics used by games with Goal::Training
+#[derive(Clone, Default, Serialize, Deserialize, Node)]
+pub struct Training {
+    /// Indexed by player id
+    pub players: Vec<TrainingPlayer>,
+    /// The frame returned to when the snapshot is restored
+    pub snapshot_frame: Option<usize>,
+    /// The player whose damage is changed by the damage hotkeys
+    pub selected_player: usize,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, Node)]
+pub struct TrainingPlayer {
+    /// The players damage is set to this on the next frame
+    pub damage: Option<f32>,
+    /// Keep setting the players damage to `damage` every frame instead of only once
+    pub lock_damage: bool,
+    combo: Combo,
+}
+
+/// The hits a player has taken without escaping hitstun
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Combo {
+    pub hits: u64,
+    pub damage: f32,
+    pub last_hit_damage: f32,
+    pub last_hit_knockback: f32,
+    pub last_hit_hitstun: u64,
+}
+
+impl Training {
+    pub fn new(players: usize) -> Training {
+        Training {
+            players: vec![TrainingPlayer::default(); players],
+            snapshot_frame: None,
+            selected_player: 0,
+        }
+    }
+
+    /// Select the next player to be changed by the damage hotkeys
+    pub fn select_next_player(&mut self) {
+        if !self.players.is_empty() {
+            self.selected_player = (self.selected_player + 1) % self.players.len();
+        }
+    }
+
+    /// Changes the damage of the selected player, starting from its current damage if it has no damage set.
+    /// Returns the new damage.
+    pub fn change_damage(&mut self, entities: &Entities, change: f32) -> Option<f32> {
+        let current = player_damage(entities, self.selected_player)?;
+        let training_player = self.players.get_mut(self.selected_player)?;
+        let damage = (training_player.damage.unwrap_or(current) + change)
+            .max(0.0)
+            .min(999.0);
+        training_player.damage = Some(damage);
+        Some(damage)
+    }
+
+    /// Toggles keeping the selected player at its set damage, its current damage is used if it has no damage set.
+    /// Returns true if the damage is now locked.
+    pub fn toggle_lock_damage(&mut self, entities: &Entities) -> Option<bool> {
+        let current = player_damage(entities, self.selected_player)?;
+        let training_player = self.players.get_mut(self.selected_player)?;
+        training_player.lock_damage = !training_player.lock_damage;
+        if training_player.lock_damage && training_player.damage.is_none() {
+            training_player.damage = Some(current);
+        }
+        Some(training_player.lock_damage)
+    }
+
+    /// The most recent combo taken by the player, None until they are first hit
+    pub fn combo(&self, player_id: usize) -> Option<Combo> {
+        self.players
+            .get(player_id)
+            .map(|x| x.combo.clone())
+            .filter(|x| x.hits > 0)
+    }
+
+    pub fn reset_combos(&mut self) {
+        for player in &mut self.players {
+            player.combo = Combo::default();
+        }
+    }
+
+    /// Compares every players state before and after a frame was stepped to count the hits they took
+    pub fn update_combos(&mut self, before: &Entities, after: &Entities) {
+        let before: HashMap<usize, (&Player, &str)> = before
+            .values()
+            .filter_map(|x| {
+                x.ty.get_player()
+                    .map(|player| (player.id, (player, x.state.action.as_ref())))
+            })
+            .collect();
+
+        for entity in after.values() {
+            let player = match entity.ty.get_player() {
+                Some(player) => player,
+                None => continue,
+            };
+            let (prev_player, prev_action) = match before.get(&player.id) {
+                Some(prev) => *prev,
+                None => continue,
+            };
+            let combo = match self.players.get_mut(player.id) {
+                Some(training_player) => &mut training_player.combo,
+                None => continue,
+            };
+
+            let damage = player.body.damage - prev_player.body.damage;
+            if damage > 0.0 {
+                // A hit only continues the combo if the player could not act before it
+                let in_hitstun = matches!(
+                    PlayerAction::from_str(prev_action),
+                    Ok(PlayerAction::Damage) | Ok(PlayerAction::DamageFly)
+                );
+                if !in_hitstun {
+                    *combo = Combo::default();
+                }
+                combo.hits += 1;
+                combo.damage += damage;
+                combo.last_hit_damage = damage;
+                combo.last_hit_knockback = player.body.hit_knockback.unwrap_or(0.0);
+                combo.last_hit_hitstun = player.hitstun.ceil() as u64;
+            }
+        }
+    }
+
+    /// Sets the damage of every player that has a damage set, clearing it unless it is locked
+    pub fn apply_damage(&mut self, entities: &mut Entities) {
+        for entity in entities.values_mut() {
+            // items and projectiles are owned by a player but dont share its damage
+            let player_id = match entity.ty.get_player() {
+                Some(player) => player.id,
+                None => continue,
+            };
+            let training_player = match self.players.get_mut(player_id) {
+                Some(training_player) => training_player,
+                None => continue,
+            };
+            if let (Some(damage), Some(body)) = (training_player.damage, entity.body_mut()) {
+                body.damage = damage;
+                if !training_player.lock_damage {
+                    training_player.damage = None;
+                }
+            }
+        }
+    }
+}
+
+fn player_damage(entities: &Entities, player_id: usize) -> Option<f32> {
+    entities
+        .values()
+        .filter_map(|x| x.ty.get_player())
+        .find(|x| x.id == player_id)
+        .map(|x| x.body.damage)
+}
@@ -0,0 +1,249 @@
+use crate::entity::Entities;
+use crate::game::{Game, GameSetup, GameState};
+use crate::replays::{self, Replay};
+use crate::rules::Goal;
+use crate::simulate::Replayer;
+use canon_collision_lib::input::Input;
+use canon_collision_lib::network;
+use canon_collision_lib::package::Package;
+use canon_collision_lib::stage::Stage;
+
+use serde_json::Value;
+
+/// The maximum number of differing fields listed for a divergent frame
+const MAX_DIFF_LINES: usize = 50;
+
+/// The state of every frame as it was originally played, starting at frame 0
+pub enum RecordedStates<'a> {
+    /// Only the state hashes were recorded, so the differing fields of a divergent frame are unknown
+    Hashes(&'a [u32]),
+    Full {
+        entities: &'a [Entities],
+        stages: &'a [Stage],
+    },
+}
+
+impl<'a> RecordedStates<'a> {
+    fn len(&self) -> usize {
+        match self {
+            RecordedStates::Hashes(hashes) => hashes.len(),
+            RecordedStates::Full { entities, stages } => entities.len().min(stages.len()),
+        }
+    }
+}
+
+/// The first frame where the resimulated state differs from the recorded state
+pub struct Divergence {
+    pub frame: usize,
+    /// Each line describes a differing field as `path: recorded -> resimulated`.
+    /// None if only the state hashes were recorded.
+    pub diff: Option<Vec<String>>,
+}
+
+impl Divergence {
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "The resimulated state diverged from the recorded state on frame {}",
+            self.frame
+        );
+        match &self.diff {
+            Some(diff) if diff.is_empty() => report.push_str(
+                "\nNo differing fields were found, the difference is only visible in the state hash (e.g. -0.0 vs 0.0)",
+            ),
+            Some(diff) => {
+                for line in diff {
+                    report.push_str("\n    ");
+                    report.push_str(line);
+                }
+                if diff.len() >= MAX_DIFF_LINES {
+                    report.push_str("\n    ...");
+                }
+            }
+            None => report.push_str(
+                "\nThe differing fields are unknown because only the state hash of each frame was recorded",
+            ),
+        }
+        report
+    }
+}
+
+/// Verify that the replay in the replays folder resimulates identically to how it was originally played
+pub fn run_replay(name: &str) -> Result<(), String> {
+    let package = Package::find_package_in_parent_dirs()
+        .and_then(Package::open)
+        .ok_or("Could not load package/ in current directory or any of its parent directories.")?;
+    let mut replay = replays::load_replay(name)?;
+    replay.check_compatible(&package)?;
+
+    let state_hashes = std::mem::take(&mut replay.state_hashes);
+    if state_hashes.is_empty() {
+        return Err(format!(
+            "The replay '{}' cannot be verified because its earliest frames were removed from the history before it was saved",
+            name
+        ));
+    }
+
+    let recorded = RecordedStates::Hashes(&state_hashes);
+    match verify(package, replay.into_game_setup(), recorded) {
+        Ok(frames) => {
+            println!(
+                "Replay '{}' verified, all {} frames resimulated identically",
+                name, frames
+            );
+            Ok(())
+        }
+        Err(divergence) => Err(divergence.report()),
+    }
+}
+
+/// Verify that the inputs of the game resimulate the history it has recorded so far, the result is logged.
+pub fn log_verify_game(game: &Game, input: &Input) {
+    if let GameState::Netplay = game.state {
+        error!("Cannot verify the replay of a netplay game");
+        return;
+    }
+    if let Goal::Training = game.rules.goal {
+        error!(
+            "Cannot verify the replay of a training game as the training settings are not recorded"
+        );
+        return;
+    }
+    if game.deleted_history_frames > 0 {
+        error!("Cannot verify the replay because its earliest frames have been removed from the history");
+        return;
+    }
+
+    let mut entities = game.entity_history();
+    entities.push(game.entities());
+    let mut stages = game.stage_history.clone();
+    stages.push(game.stage.clone());
+    let recorded = RecordedStates::Full {
+        entities: &entities,
+        stages: &stages,
+    };
+
+    let setup = Replay::new(game, input).into_game_setup();
+    match verify(game.package.clone(), setup, recorded) {
+        Ok(frames) => info!(
+            "Replay verified, all {} frames resimulated identically",
+            frames
+        ),
+        Err(divergence) => error!("{}", divergence.report()),
+    }
+}
+
+/// Resimulate the game from the input history of the setup and compare the state of every frame to the recorded state.
+/// Returns the number of frames that were compared.
+pub fn verify(
+    package: Package,
+    setup: GameSetup,
+    recorded: RecordedStates,
+) -> Result<usize, Divergence> {
+    let mut replayer = Replayer::new(package, setup);
+    let frames = recorded.len();
+    for frame in 0..frames {
+        compare_frame(replayer.game(), frame, &recorded)?;
+
+        if frame + 1 < frames && !replayer.step() {
+            // The inputs ran out before the recorded states did
+            return Ok(frame + 1);
+        }
+    }
+    Ok(frames)
+}
+
+fn compare_frame(game: &Game, frame: usize, recorded: &RecordedStates) -> Result<(), Divergence> {
+    match recorded {
+        RecordedStates::Hashes(hashes) => {
+            if game.state_hash() != hashes[frame] {
+                return Err(Divergence { frame, diff: None });
+            }
+        }
+        RecordedStates::Full { entities, stages } => {
+            let recorded_state = (&entities[frame], &stages[frame]);
+            if game.state_hash() != network::state_hash(&recorded_state) {
+                let recorded_state = serde_json::to_value(&recorded_state).unwrap();
+                let resimulated_state =
+                    serde_json::to_value(&(game.entities(), &game.stage)).unwrap();
+                let mut diff = vec![];
+                diff_values("", &recorded_state, &resimulated_state, &mut diff);
+                return Err(Divergence {
+                    frame,
+                    diff: Some(diff),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Appends a line for every field that differs between the two values, until MAX_DIFF_LINES is reached.
+fn diff_values(path: &str, recorded: &Value, resimulated: &Value, diff: &mut Vec<String>) {
+    if diff.len() >= MAX_DIFF_LINES {
+        return;
+    }
+
+    match (recorded, resimulated) {
+        (Value::Object(recorded), Value::Object(resimulated)) => {
+            for (key, recorded_value) in recorded {
+                let path = format!("{}.{}", path, key);
+                match resimulated.get(key) {
+                    Some(resimulated_value) => {
+                        diff_values(&path, recorded_value, resimulated_value, diff)
+                    }
+                    None => diff.push(format!("{}: {} -> missing", path, recorded_value)),
+                }
+            }
+            for (key, resimulated_value) in resimulated {
+                if !recorded.contains_key(key) {
+                    diff.push(format!(
+                        "{}.{}: missing -> {}",
+                        path, key, resimulated_value
+                    ));
+                }
+            }
+        }
+        (Value::Array(recorded), Value::Array(resimulated)) => {
+            for (i, (recorded_value, resimulated_value)) in
+                recorded.iter().zip(resimulated.iter()).enumerate()
+            {
+                let path = format!("{}[{}]", path, i);
+                diff_values(&path, recorded_value, resimulated_value, diff);
+            }
+            if recorded.len() != resimulated.len() {
+                diff.push(format!(
+                    "{}: length {} -> {}",
+                    path,
+                    recorded.len(),
+                    resimulated.len()
+                ));
+            }
+        }
+        (recorded, resimulated) => {
+            if recorded != resimulated {
+                diff.push(format!("{}: {} -> {}", path, recorded, resimulated));
+            }
+        }
+    }
+    diff.truncate(MAX_DIFF_LINES);
+}
+
+#[test]
+fn diff_values_lists_differing_fields() {
+    let recorded = serde_json::json!([{ "x": 1.0, "action": "Idle", "hitboxes": [1, 2] }]);
+    let resimulated = serde_json::json!([{ "x": 1.5, "action": "Idle", "hitboxes": [1] }]);
+    let mut diff = vec![];
+    diff_values("", &recorded, &resimulated, &mut diff);
+    diff.sort();
+    assert_eq!(
+        diff,
+        vec![
+            String::from("[0].hitboxes: length 2 -> 1"),
+            String::from("[0].x: 1.0 -> 1.5"),
+        ]
+    );
+
+    let mut diff = vec![];
+    diff_values("", &recorded, &recorded, &mut diff);
+    assert!(diff.is_empty());
+}
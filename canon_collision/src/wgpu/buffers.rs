@@ -604,4 +604,54 @@ impl Buffers {
 
         Buffers::new(device, &vertices, &indices)
     }
+
+    /// Creates a single circle with radius 1 around the origin, it is drawn in the color of the HitboxUniform
+    pub fn new_uniform_circle(device: &Device) -> Rc<Buffers> {
+        let mut vertices: Vec<Vertex> = vec![];
+        let mut indices: Vec<u16> = vec![];
+
+        let iterations = 40;
+
+        vertices.push(Vertex {
+            position: [0.0, 0.0],
+            edge: 0.0,
+            render_id: 0,
+        });
+        for i in 0..iterations {
+            let angle = i as f32 * 2.0 * consts::PI / (iterations as f32);
+            let (sin, cos) = angle.sin_cos();
+            vertices.push(Vertex {
+                position: [cos, sin],
+                edge: 0.0,
+                render_id: 0,
+            });
+            indices.push(0);
+            indices.push(i + 1);
+            indices.push((i + 1) % iterations + 1);
+        }
+
+        Buffers::new(device, &vertices, &indices)
+    }
+
+    /// Creates a square from (0, 0) to (1, 1), it is drawn in the color of the HitboxUniform
+    pub fn new_uniform_square(device: &Device) -> Rc<Buffers> {
+        let vertex = |x, y| Vertex {
+            position: [x, y],
+            edge: 0.0,
+            render_id: 0,
+        };
+        let vertices: [Vertex; 4] = [
+            vertex(0.0, 0.0),
+            vertex(1.0, 0.0),
+            vertex(1.0, 1.0),
+            vertex(0.0, 1.0),
+        ];
+
+        let indices: [u16; 6] = [
+            0, 1, 2, // 1
+            0, 2, 3, // 2
+        ];
+
+        Buffers::new(device, &vertices, &indices)
+    }
 }
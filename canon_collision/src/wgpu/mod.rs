@@ -2,8 +2,10 @@ mod animation;
 mod buffers;
 mod model3d;
 
+use crate::ai;
 use crate::audio::BGMMetadata;
 use crate::camera::Camera;
+use crate::entity::fighters::player::RenderInputDisplay;
 use crate::entity::{RenderEntityFrame, RenderEntityType};
 use crate::game::{GameState, RenderGame, RenderObject};
 use crate::graphics::{self, GraphicsMessage, Render, RenderType};
@@ -76,6 +78,10 @@ pub struct WgpuGraphics {
     frame_durations: Vec<Duration>,
     fps: String,
     bgm_metadata: Option<(BGMMetadata, Instant)>,
+    /// Shared by every circle drawn on top of the scene, positioned and colored by its uniform
+    screen_circle: Rc<Buffers>,
+    /// Shared by every rect drawn on top of the scene, positioned and colored by its uniform
+    screen_square: Rc<Buffers>,
     width: u32,
     height: u32,
 }
@@ -509,6 +515,8 @@ impl WgpuGraphics {
             usage: wgpu::BufferUsages::UNIFORM,
         });
         let uniforms_buffer_len = 0;
+        let screen_circle = Buffers::new_uniform_circle(&device);
+        let screen_square = Buffers::new_uniform_square(&device);
 
         WgpuGraphics {
             package: None,
@@ -540,6 +548,8 @@ impl WgpuGraphics {
             frame_durations: vec![],
             fps: "".into(),
             bgm_metadata: None,
+            screen_circle,
+            screen_square,
             width,
             height,
         }
@@ -965,6 +975,24 @@ impl WgpuGraphics {
                                 screen_position: (location, self.height as f32 - 117.0),
                                 ..Section::default()
                             });
+
+                            if let Some(combo) = &player.combo {
+                                let combo_string = format!(
+                                    "{} hit combo {:.1}%\nLast hit: {:.1}% {:.0}kb {}f hitstun",
+                                    combo.hits,
+                                    combo.damage,
+                                    combo.last_hit_damage,
+                                    combo.last_hit_knockback,
+                                    combo.last_hit_hitstun
+                                );
+                                self.glyph_brush.queue(Section {
+                                    text: vec![Text::new(combo_string.as_ref())
+                                        .with_color(color)
+                                        .with_scale(18.0)],
+                                    screen_position: (location + 10.0, self.height as f32 - 180.0),
+                                    ..Section::default()
+                                });
+                            }
                         }
                     }
                 }
@@ -1673,9 +1701,195 @@ impl WgpuGraphics {
             }
         }
 
+        // The input display is drawn over the top of everything
+        if command_output.is_empty() {
+            draws.extend(self.input_displays_render(&render.entities));
+        }
+
         draws
     }
 
+    /// Draws the input display of each player above their damage, using the same layout as game_hud_render
+    fn input_displays_render(&self, objects: &[RenderObject]) -> Vec<Draw> {
+        let mut draws = vec![];
+        let mut entities = 0;
+        for object in objects {
+            if let RenderObject::Entity(entity) = object {
+                if let RenderEntityType::Player(_) = &entity.render_type {
+                    entities += 1;
+                }
+            }
+        }
+        let distance = (self.width / (entities + 1)) as f32;
+
+        let mut location = -100.0;
+        for object in objects {
+            if let RenderObject::Entity(entity) = object {
+                location += distance;
+                if let RenderEntityType::Player(player) = &entity.render_type {
+                    if let Some(input_display) = &player.input_display {
+                        let c = entity.fighter_color;
+                        let color = [c[0], c[1], c[2], 1.0];
+                        draws.extend(self.draw_input_display(
+                            input_display,
+                            location,
+                            self.height as f32 - 240.0,
+                            color,
+                        ));
+                    }
+                }
+            }
+        }
+        draws
+    }
+
+    /// Draws a controller overlay with its top left corner at the specified pixel.
+    /// The stick leaves a trail of its previous positions, fading with age.
+    fn draw_input_display(
+        &self,
+        display: &RenderInputDisplay,
+        x: f32,
+        y: f32,
+        color: [f32; 4],
+    ) -> Vec<Draw> {
+        let mut draws = vec![];
+        let current = match display.history.first() {
+            Some(current) => current,
+            None => return draws,
+        };
+        let background = [0.1, 0.1, 0.1, 0.7];
+        let released = [0.3, 0.3, 0.3, 0.8];
+
+        // stick
+        let stick = (x + 40.0, y + 60.0);
+        draws.push(self.draw_screen_circle(stick, 35.0, background));
+        for (i, input) in display.history.iter().enumerate().rev() {
+            let alpha = 1.0 - i as f32 / display.history.len() as f32;
+            let radius = if i == 0 { 9.0 } else { 4.0 };
+            let position = (
+                stick.0 + input.stick_x * 30.0,
+                stick.1 - input.stick_y * 30.0,
+            );
+            draws.push(self.draw_screen_circle(
+                position,
+                radius,
+                [color[0], color[1], color[2], alpha],
+            ));
+        }
+
+        // c-stick
+        let c_stick = (x + 115.0, y + 75.0);
+        draws.push(self.draw_screen_circle(c_stick, 20.0, background));
+        let position = (
+            c_stick.0 + current.c_stick_x * 15.0,
+            c_stick.1 - current.c_stick_y * 15.0,
+        );
+        draws.push(self.draw_screen_circle(position, 7.0, [1.0, 0.85, 0.0, 1.0]));
+
+        // triggers are filled by their analog value, and completely filled when the digital button is pressed
+        let triggers = [
+            (current.l_trigger, current.l),
+            (current.r_trigger, current.r),
+        ];
+        for (i, (analog, digital)) in triggers.iter().enumerate() {
+            let left = x + i as f32 * 120.0;
+            draws.push(self.draw_screen_rect(left, y, left + 80.0, y + 10.0, released));
+            let (fill, fill_color) = if *digital {
+                (1.0, [1.0, 1.0, 1.0, 1.0])
+            } else {
+                (analog.max(0.0).min(1.0), [0.7, 0.7, 0.7, 1.0])
+            };
+            draws.push(self.draw_screen_rect(left, y, left + 80.0 * fill, y + 10.0, fill_color));
+        }
+
+        // buttons
+        let buttons = [
+            (current.a, (x + 175.0, y + 60.0), 12.0, [0.0, 0.8, 0.5, 1.0]),
+            (current.b, (x + 153.0, y + 75.0), 8.0, [0.9, 0.1, 0.1, 1.0]),
+            (current.x, (x + 195.0, y + 50.0), 7.0, [0.9, 0.9, 0.9, 1.0]),
+            (current.y, (x + 170.0, y + 38.0), 7.0, [0.9, 0.9, 0.9, 1.0]),
+            (current.z, (x + 190.0, y + 26.0), 6.0, [0.6, 0.2, 0.9, 1.0]),
+            (
+                current.start,
+                (x + 92.0, y + 30.0),
+                5.0,
+                [0.9, 0.9, 0.9, 1.0],
+            ),
+            (current.up, (x + 80.0, y + 40.0), 4.0, [0.9, 0.9, 0.9, 1.0]),
+            (
+                current.down,
+                (x + 80.0, y + 56.0),
+                4.0,
+                [0.9, 0.9, 0.9, 1.0],
+            ),
+            (
+                current.left,
+                (x + 72.0, y + 48.0),
+                4.0,
+                [0.9, 0.9, 0.9, 1.0],
+            ),
+            (
+                current.right,
+                (x + 88.0, y + 48.0),
+                4.0,
+                [0.9, 0.9, 0.9, 1.0],
+            ),
+        ];
+        for (pressed, center, radius, pressed_color) in buttons.iter() {
+            let color = if *pressed { *pressed_color } else { released };
+            draws.push(self.draw_screen_circle(*center, *radius, color));
+        }
+
+        draws
+    }
+
+    /// Converts a pixel position into a position in normalized device coordinates
+    fn pixel_to_ndc(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            x / self.width as f32 * 2.0 - 1.0,
+            1.0 - y / self.height as f32 * 2.0,
+        )
+    }
+
+    /// Draws a circle on top of the scene, the center and radius are in pixels
+    fn draw_screen_circle(&self, center: (f32, f32), radius: f32, color: [f32; 4]) -> Draw {
+        let (x, y) = self.pixel_to_ndc(center);
+        let transform = Matrix4::from_translation(Vector3::new(x, y, 0.0))
+            * Matrix4::from_nonuniform_scale(
+                radius * 2.0 / self.width as f32,
+                radius * 2.0 / self.height as f32,
+                1.0,
+            );
+        let uniform = HitboxUniform {
+            edge_color: color,
+            color,
+            transform: transform.into(),
+        };
+
+        Draw {
+            ty: DrawType::Hitbox { uniform },
+            buffers: self.screen_circle.clone(),
+        }
+    }
+
+    /// Draws a rect on top of the scene, the corners are in pixels
+    fn draw_screen_rect(&self, x1: f32, y1: f32, x2: f32, y2: f32, color: [f32; 4]) -> Draw {
+        let (x1, y1) = self.pixel_to_ndc((x1, y1));
+        let (x2, y2) = self.pixel_to_ndc((x2, y2));
+        let transform = Matrix4::from_translation(Vector3::new(x1, y1, 0.0))
+            * Matrix4::from_nonuniform_scale(x2 - x1, y2 - y1, 1.0);
+        let uniform = HitboxUniform {
+            edge_color: color,
+            color,
+            transform: transform.into(),
+        };
+
+        Draw {
+            ty: DrawType::Hitbox { uniform },
+            buffers: self.screen_square.clone(),
+        }
+    }
+
     fn menu_render(&mut self, render: RenderMenu, command_output: &[String]) -> Vec<Draw> {
         self.fps_render();
         let mut draws = vec![];
@@ -1866,7 +2080,7 @@ impl WgpuGraphics {
             ..Section::default()
         });
 
-        let modes = vec!["Local", "Netplay", "Replays"];
+        let modes = vec!["Local", "Training", "Netplay", "Replays"];
         for (mode_i, name) in modes.iter().enumerate() {
             let size = 26.0; // TODO: determine from width/height of screen and start/end pos
             let x_offset = if mode_i == selection { 0.1 } else { 0.0 };
@@ -1963,7 +2177,10 @@ impl WgpuGraphics {
             };
             let name = match selection.ui {
                 PlayerSelectUi::CpuAi(_) => "CPU AI".to_string(),
-                PlayerSelectUi::CpuFighter(_) => "CPU Fighter".to_string(),
+                PlayerSelectUi::CpuFighter(_) => format!(
+                    "CPU {}",
+                    ai::AI_PROFILES[selection.cpu_ai.unwrap_or(ai::DEFAULT_AI_PROFILE)].name
+                ),
                 PlayerSelectUi::HumanFighter(_) => format!("Port #{}", controller_i + 1),
                 PlayerSelectUi::HumanTeam(_) => format!("Port #{} Team", controller_i + 1),
                 PlayerSelectUi::CpuTeam(_) => "CPU Team".to_string(),
@@ -1999,6 +2216,7 @@ impl WgpuGraphics {
                 options.push(String::from("Return"));
             }
             PlayerSelectUi::CpuAi(_) => {
+                options.extend(ai::AI_PROFILES.iter().map(|x| x.name.to_string()));
                 options.push(String::from("Return"));
             }
             PlayerSelectUi::HumanUnplugged => unreachable!(),
@@ -2028,6 +2246,11 @@ impl WgpuGraphics {
                         color = graphics::get_team_color4(option_i);
                     }
                 }
+                PlayerSelectUi::CpuAi(_) => {
+                    if selection.cpu_ai.unwrap_or(ai::DEFAULT_AI_PROFILE) == option_i {
+                        color = graphics::get_team_color4(selection.team);
+                    }
+                }
                 _ => {}
             }
             self.glyph_brush.queue(Section {
@@ -2170,6 +2393,11 @@ impl WgpuGraphics {
         let color = graphics::get_team_color4(result.team);
         let x = (start_x + 0.05) * self.width as f32;
         let y = 30.0;
+        let self_destructs = result.deaths.iter().filter(|x| x.player.is_none()).count();
+        let score = match result.score {
+            Some(score) => format!("\nScore: {}", score),
+            None => String::new(),
+        };
         self.glyph_brush.queue(Section {
             text: vec![
                 Text::new((result.place + 1).to_string().as_ref())
@@ -2179,13 +2407,16 @@ impl WgpuGraphics {
                     format!(
                         "
 
-{}
+{}{}
 Kills: {}
 Deaths: {}
+Self-Destructs: {}
 L-Cancel Success: {}%",
                         fighter_name,
+                        score,
                         result.kills.len(),
                         result.deaths.len(),
+                        self_destructs,
                         result.lcancel_percent
                     )
                     .as_str(),
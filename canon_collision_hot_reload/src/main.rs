@@ -1,4 +1,5 @@
 use std::env;
+use std::fs;
 use std::io::Read;
 use std::io::Write;
 use std::net::TcpStream;
@@ -8,7 +9,7 @@ use std::sync::mpsc::Receiver;
 
 use hotwatch::{Event, Hotwatch};
 
-use canon_collision_lib::replays_files;
+use canon_collision_lib::files;
 
 fn main() {
     let (tx, rx) = std::sync::mpsc::channel();
@@ -64,26 +65,24 @@ fn run(rx: Receiver<Event>) {
                 // if the process is running then hot reload it.
                 // otherwise launch from scratch
                 if is_process_running(&mut process) {
-                    // This doesnt block because the replay save needs to be delayed until the Game::step where it has access to input data.
-                    assert!(send_to_cc(":save_replay"));
+                    // This doesnt block because the snapshot save needs to be delayed until the Game::step where it has access to input data.
+                    assert!(send_to_cc(":save_hot_reload"));
                     // This blocks on the first command because we cant run another command until the next Game::step has occured.
                     assert!(send_to_cc(":help"));
 
-                    let replays = replays_files::get_replay_names();
-                    assert_ne!(replays.len(), 0, "replay was missing for some reason");
-                    let latest_replay = &replays[0];
-                    let latest_replay_filename = format!("{}.zip", latest_replay);
+                    let snapshot_path = files::get_hot_reload_path();
+                    assert!(snapshot_path.exists(), "hot reload snapshot was missing for some reason");
 
                     if let Some(mut x) = process.take() { x.kill().unwrap() }
 
                     // relaunch
-                    process = launch(profile_arg, &["--replay", &latest_replay_filename]);
+                    process = launch(profile_arg, &["--hotreload"]);
 
-                    // busy loop until the replay is loaded or the process died, probably due to changes in the replay structure.
+                    // busy loop until the snapshot is loaded or the process died, probably due to changes in the snapshot structure.
                     while !send_to_cc(":help") && is_process_running(&mut process) {}
 
-                    // cleanup the replay
-                    replays_files::delete_replay(latest_replay);
+                    // cleanup the snapshot
+                    fs::remove_file(snapshot_path).ok();
                 } else {
                     process = launch(profile_arg, &pass_through_args);
                 }
@@ -1,4 +1,5 @@
 use crate::files;
+use crate::network::{DEFAULT_MATCHMAKING_HOST, DEFAULT_PORT};
 
 use std::path::PathBuf;
 
@@ -6,9 +7,26 @@ use serde_json;
 use treeflection::{Node, NodeRunner, NodeToken};
 
 #[derive(Clone, Serialize, Deserialize, Node)]
+#[serde(default)]
 pub struct Config {
     pub netplay_region: Option<String>,
+    /// The UDP port used to send and receive netplay packets
+    pub netplay_port: u16,
+    /// The address of the server used to find online matches
+    pub netplay_matchmaking_host: String,
+    /// The number of frames a spectator is kept behind the players, so the game keeps playing smoothly when packets are late
+    pub netplay_spectator_delay: usize,
+    /// The number of frames local inputs are delayed by during netplay, None chooses a delay from the ping to peers
+    pub netplay_input_delay: Option<usize>,
+    /// Netplay sessions with an average round trip ping above this many milliseconds are disconnected
+    pub netplay_max_ping: f32,
     pub auto_save_replay: bool,
+    /// The file name of saved replays, `{timestamp}`, `{stage}` and `{players}` are replaced with the details of the replay
+    pub replay_name_template: String,
+    /// When more replays than this are saved, the oldest replays are deleted. Favourite replays are not counted.
+    pub replay_max_count: Option<usize>,
+    /// When the replays use more than this many megabytes, the oldest replays are deleted. Favourite replays are not counted.
+    pub replay_max_megabytes: Option<u64>,
     pub verify_package_hashes: bool,
     pub fullscreen: bool,
 }
@@ -42,7 +60,15 @@ impl Default for Config {
     fn default() -> Config {
         Config {
             netplay_region: None,
+            netplay_port: DEFAULT_PORT,
+            netplay_matchmaking_host: DEFAULT_MATCHMAKING_HOST.into(),
+            netplay_spectator_delay: 180,
+            netplay_input_delay: None,
+            netplay_max_ping: 100.0,
             auto_save_replay: false,
+            replay_name_template: String::from("{timestamp} {stage} {players}"),
+            replay_max_count: None,
+            replay_max_megabytes: None,
             verify_package_hashes: true,
             fullscreen: false,
         }
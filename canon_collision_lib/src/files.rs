@@ -105,3 +105,10 @@ pub fn get_path() -> PathBuf {
     data_local.push("CanonCollision");
     data_local
 }
+
+/// The hot reload snapshot contains the entire game state so it is kept separate from replays
+pub fn get_hot_reload_path() -> PathBuf {
+    let mut path = get_path();
+    path.push("hot_reload.bin");
+    path
+}
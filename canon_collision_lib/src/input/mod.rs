@@ -23,11 +23,17 @@ pub struct Input {
     // game past and (potentially) future inputs, frame 0 has index 2
     // structure: frames Vec<controllers Vec<ControllerInput>>
     game_inputs: Vec<Vec<ControllerInput>>,
+    /// The inputs of every player in a netplay game, including remote players, in the same order as the game inputs of a local game.
+    /// Used instead of game_inputs when saving a netplay game as a replay.
+    /// structure: frames Vec<controllers Vec<ControllerInput>>
+    netplay_history: Vec<Vec<ControllerInput>>,
     current_inputs: Vec<ControllerInput>, // inputs for this frame
     prev_start: bool,
     input_sources: Vec<InputSource>,
-    _rusb_context: Context,
-    gilrs: Gilrs,
+    /// None when headless
+    _rusb_context: Option<Context>,
+    /// None when headless
+    gilrs: Option<Gilrs>,
     controller_maps: ControllerMaps,
     pub events: Vec<Event>,
 }
@@ -48,16 +54,44 @@ impl Input {
 
         Input {
             game_inputs: vec![],
+            netplay_history: vec![],
             current_inputs: vec![],
             events: vec![],
             prev_start: false,
             input_sources,
-            _rusb_context,
-            gilrs,
+            _rusb_context: Some(_rusb_context),
+            gilrs: Some(gilrs),
             controller_maps,
         }
     }
 
+    /// Create an Input that never reads from controllers, the inputs of each frame are instead provided to step_headless.
+    pub fn headless() -> Input {
+        Input {
+            game_inputs: vec![],
+            netplay_history: vec![],
+            current_inputs: vec![],
+            events: vec![],
+            prev_start: false,
+            input_sources: vec![],
+            _rusb_context: None,
+            gilrs: None,
+            controller_maps: ControllerMaps::load(),
+        }
+    }
+
+    /// Call this once every frame instead of step, when headless
+    pub fn step_headless(
+        &mut self,
+        mut inputs: Vec<ControllerInput>,
+        ai_inputs: &[ControllerInput],
+    ) {
+        inputs.extend_from_slice(ai_inputs);
+
+        self.prev_start = self.current_inputs.iter().any(|x| x.start);
+        self.current_inputs = inputs;
+    }
+
     /// Call this once every frame
     pub fn step(
         &mut self,
@@ -80,21 +114,22 @@ impl Input {
         }
 
         self.events.clear();
-        while let Some(ev) = self.gilrs.next_event() {
-            self.events.push(ev);
-        }
-        self.events.sort_by_key(|x| x.time);
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(ev) = gilrs.next_event() {
+                self.events.push(ev);
+            }
+            self.events.sort_by_key(|x| x.time);
 
-        let mut generic_controllers = vec![];
-        for input_source in &self.input_sources {
-            if let InputSource::GenericController(controller) = input_source {
-                generic_controllers.push(controller);
+            let mut generic_controllers = vec![];
+            for input_source in &self.input_sources {
+                if let InputSource::GenericController(controller) = input_source {
+                    generic_controllers.push(controller);
+                }
+            }
+            for controller in GenericController::get_controllers(gilrs, &generic_controllers) {
+                self.input_sources
+                    .push(InputSource::GenericController(controller));
             }
-        }
-        for controller in GenericController::get_controllers(&mut self.gilrs, &generic_controllers)
-        {
-            self.input_sources
-                .push(InputSource::GenericController(controller));
         }
 
         // read input from controllers
@@ -112,7 +147,9 @@ impl Input {
                         .map(|x| &x.event)
                         .cloned()
                         .collect();
-                    let gamepad = &self.gilrs.gamepad(controller.index).unwrap(); // Old gamepads stick around forever so its fine to unwrap.
+                    // Generic controllers only exist when gilrs exists
+                    let gilrs = self.gilrs.as_ref().unwrap();
+                    let gamepad = &gilrs.gamepad(controller.index).unwrap(); // Old gamepads stick around forever so its fine to unwrap.
                     let maps = &self.controller_maps.maps;
                     inputs.push(controller.read(maps, events, gamepad));
                 }
@@ -146,17 +183,51 @@ impl Input {
     /// Reset the game input history
     pub fn reset_history(&mut self) {
         self.game_inputs.clear();
+        self.netplay_history.clear();
         self.prev_start = false;
     }
 
     /// Set the game input history
     pub fn set_history(&mut self, history: Vec<Vec<ControllerInput>>) {
         self.game_inputs = history;
+        self.netplay_history.clear();
     }
 
-    /// Get the game input history
+    /// Erase the inputs of every frame after the specified frame
+    pub fn truncate_history(&mut self, frame: usize) {
+        self.game_inputs.truncate(frame + 1);
+    }
+
+    /// Get the game input history.
+    /// For a netplay game this contains the inputs of every player, so it can be resimulated without the network.
     pub fn get_history(&self) -> Vec<Vec<ControllerInput>> {
-        self.game_inputs.clone()
+        if self.netplay_history.is_empty() {
+            self.game_inputs.clone()
+        } else {
+            self.netplay_history.clone()
+        }
+    }
+
+    /// Call this from netplay game update logic with the inputs returned by players for every stepped frame.
+    /// Resimulated frames replace the inputs previously recorded for them.
+    pub fn record_netplay_frame(&mut self, frame: usize, player_inputs: &[PlayerInput]) {
+        // Frame n uses the inputs at index n - 1, so frame 0 has no inputs to record
+        if frame == 0 {
+            return;
+        }
+        self.netplay_history.truncate(frame - 1);
+        while self.netplay_history.len() < frame - 1 {
+            self.netplay_history.push(vec![]);
+        }
+        self.netplay_history.push(
+            player_inputs
+                .iter()
+                .map(|x| match x.history.first() {
+                    Some(input) if x.plugged_in => *input,
+                    _ => ControllerInput::empty(),
+                })
+                .collect(),
+        );
     }
 
     /// Call this once from the game update logic only
@@ -177,24 +248,49 @@ impl Input {
     /// Return game inputs at specified index into history
     pub fn players_no_log(&self, frame: usize, netplay: &Netplay) -> Vec<PlayerInput> {
         let mut result_inputs: Vec<PlayerInput> = vec![];
+        // Netplay inputs are indexed by session frame, which does not restart with each game or menu
+        let session_frame = netplay.frame_origin() + frame;
 
-        let local_index = netplay.local_index();
+        // A spectator has no local players, every player is a peer
+        let local_index = if netplay.is_spectating() {
+            None
+        } else {
+            Some(netplay.local_index())
+        };
         let mut peer_offset = 0;
         let peers_inputs = &netplay.confirmed_inputs;
         for i in 0..netplay.number_of_peers() {
-            if i == local_index {
+            if Some(i) == local_index {
                 peer_offset = 1;
 
-                for i in 0..self.current_inputs.len() {
-                    let inputs = self.get_8frames_of_input(&self.game_inputs, i, frame as i64);
+                // During netplay the local players use the inputs sent to peers, so the input delay applies to them too.
+                // AI inputs are not sent to peers, so they always come from the game input history.
+                let num_sent_controllers = match netplay.local_inputs() {
+                    Some(local_inputs) => local_inputs.last().map_or(0, |x| x.len()),
+                    None => 0,
+                };
+                // A replay has no controllers, so its controllers are counted from the history
+                let num_controllers = self
+                    .current_inputs
+                    .len()
+                    .max(self.game_inputs.last().map_or(0, |x| x.len()))
+                    .max(num_sent_controllers);
+                for i in 0..num_controllers {
+                    let inputs = match netplay.local_inputs() {
+                        Some(local_inputs) if i < num_sent_controllers => {
+                            self.get_8frames_of_input(local_inputs, i, session_frame as i64)
+                        }
+                        _ => self.get_8frames_of_input(&self.game_inputs, i, frame as i64),
+                    };
                     result_inputs.push(Input::controller_inputs_to_player_input(inputs));
                 }
             } else {
                 let peer_inputs = &peers_inputs[i - peer_offset];
                 let num_controllers = peer_inputs.last().map_or(0, |x| x.len());
                 for i in 0..num_controllers {
+                    // Frames that are not yet confirmed are predicted by get_8frames_of_input repeating the last confirmed input
                     let inputs =
-                        self.get_8frames_of_input(&peer_inputs[..], i, netplay.frame() as i64);
+                        self.get_8frames_of_input(&peer_inputs[..], i, session_frame as i64);
                     result_inputs.push(Input::controller_inputs_to_player_input(inputs));
                 }
             }
@@ -49,7 +49,7 @@ impl ControllerInput {
 }
 
 /// Internal input storage
-#[derive(Copy, Clone, Default, Serialize, Deserialize, Node)]
+#[derive(Copy, Clone, Default, PartialEq, Serialize, Deserialize, Node)]
 pub struct ControllerInput {
     pub plugged_in: bool,
 
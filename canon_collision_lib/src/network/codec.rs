@@ -0,0 +1,435 @@
+use super::{
+    InitConnection, InputConfirm, MatchMakingRequest, MatchMakingResponse, SpectatorInputs,
+    SpectatorRequest, StateHashes,
+};
+
+use std::fmt;
+
+/*  Packet Format:
+    Header:
+        2 bytes - protocol version
+        4 bytes - build id, crc32 of the build version
+        8 bytes - session id, 0 when the sender has not joined a session
+        1 byte  - message kind
+        2 bytes - payload length
+        4 bytes - crc32 of the header (excluding this field) and payload
+
+    Payload:
+        n bytes - bincode serialized message, depending on message kind:
+            0x00 - MatchMakingRequest
+            0x01 - MatchMakingResponse
+            0x02 - InitConnection
+            0x03 - Ping request: u8 ping id
+            0x04 - Ping response: u8 ping id
+            0x05 - InputConfirm
+            0x06 - StateHashes
+            0x07 - SpectatorRequest
+            0x08 - SpectatorInputs
+            0xAA - Disconnect: empty
+*/
+
+/// Increment whenever the packet format or any message changes
+pub const PROTOCOL_VERSION: u16 = 4;
+pub const HEADER_LEN: usize = 21;
+/// Large enough to hold an InputConfirm containing MAX_INPUT_FRAMES_PER_PACKET frames of inputs for 4 controllers.
+/// Fewer frames are sent per packet when there are more controllers.
+pub const MAX_PACKET_LEN: usize = 4096;
+
+pub fn build_id(build_version: &str) -> u32 {
+    crc32fast::hash(build_version.as_bytes())
+}
+
+#[derive(Clone)]
+pub enum Message {
+    MatchMakingRequest(MatchMakingRequest),
+    MatchMakingResponse(MatchMakingResponse),
+    Init(InitConnection),
+    PingRequest(u8),
+    PingResponse(u8),
+    InputConfirm(InputConfirm),
+    StateHashes(StateHashes),
+    SpectatorRequest(SpectatorRequest),
+    SpectatorInputs(SpectatorInputs),
+    Disconnect,
+}
+
+impl Message {
+    fn kind(&self) -> u8 {
+        match self {
+            Message::MatchMakingRequest(_) => 0x00,
+            Message::MatchMakingResponse(_) => 0x01,
+            Message::Init(_) => 0x02,
+            Message::PingRequest(_) => 0x03,
+            Message::PingResponse(_) => 0x04,
+            Message::InputConfirm(_) => 0x05,
+            Message::StateHashes(_) => 0x06,
+            Message::SpectatorRequest(_) => 0x07,
+            Message::SpectatorInputs(_) => 0x08,
+            Message::Disconnect => 0xAA,
+        }
+    }
+
+    fn serialize_payload(&self) -> bincode::Result<Vec<u8>> {
+        match self {
+            Message::MatchMakingRequest(x) => bincode::serialize(x),
+            Message::MatchMakingResponse(x) => bincode::serialize(x),
+            Message::Init(x) => bincode::serialize(x),
+            Message::PingRequest(x) => bincode::serialize(x),
+            Message::PingResponse(x) => bincode::serialize(x),
+            Message::InputConfirm(x) => bincode::serialize(x),
+            Message::StateHashes(x) => bincode::serialize(x),
+            Message::SpectatorRequest(x) => bincode::serialize(x),
+            Message::SpectatorInputs(x) => bincode::serialize(x),
+            Message::Disconnect => Ok(vec![]),
+        }
+    }
+
+    fn deserialize_payload(kind: u8, payload: &[u8]) -> Result<Message, DecodeError> {
+        let map_err = |err: bincode::Error| DecodeError::Payload {
+            kind,
+            error: err.to_string(),
+        };
+        Ok(match kind {
+            0x00 => Message::MatchMakingRequest(bincode::deserialize(payload).map_err(map_err)?),
+            0x01 => Message::MatchMakingResponse(bincode::deserialize(payload).map_err(map_err)?),
+            0x02 => Message::Init(bincode::deserialize(payload).map_err(map_err)?),
+            0x03 => Message::PingRequest(bincode::deserialize(payload).map_err(map_err)?),
+            0x04 => Message::PingResponse(bincode::deserialize(payload).map_err(map_err)?),
+            0x05 => Message::InputConfirm(bincode::deserialize(payload).map_err(map_err)?),
+            0x06 => Message::StateHashes(bincode::deserialize(payload).map_err(map_err)?),
+            0x07 => Message::SpectatorRequest(bincode::deserialize(payload).map_err(map_err)?),
+            0x08 => Message::SpectatorInputs(bincode::deserialize(payload).map_err(map_err)?),
+            0xAA => Message::Disconnect,
+            _ => return Err(DecodeError::UnknownMessage { kind }),
+        })
+    }
+}
+
+pub struct Packet {
+    pub build_id: u32,
+    pub session_id: u64,
+    pub message: Message,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    TooShort { len: usize },
+    ProtocolVersion { version: u16 },
+    Length { header: usize, actual: usize },
+    Checksum { header: u32, actual: u32 },
+    UnknownMessage { kind: u8 },
+    Payload { kind: u8, error: String },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EncodeError {
+    TooLong { kind: u8, len: usize },
+    Payload { kind: u8, error: String },
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::TooLong { kind, len } => write!(
+                f,
+                "message kind {:#04x} encodes to a {} byte packet which is longer than the maximum of {} bytes",
+                kind, len, MAX_PACKET_LEN
+            ),
+            EncodeError::Payload { kind, error } => write!(
+                f,
+                "failed to serialize payload of message kind {:#04x}: {}",
+                kind, error
+            ),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::TooShort { len } => write!(
+                f,
+                "packet is {} bytes which is shorter than the {} byte header",
+                len, HEADER_LEN
+            ),
+            DecodeError::ProtocolVersion { version } => write!(
+                f,
+                "packet uses protocol version {} but the local protocol version is {}",
+                version, PROTOCOL_VERSION
+            ),
+            DecodeError::Length { header, actual } => write!(
+                f,
+                "header specifies a {} byte payload but the payload is {} bytes",
+                header, actual
+            ),
+            DecodeError::Checksum { header, actual } => write!(
+                f,
+                "header specifies checksum {:#010x} but the packet checksum is {:#010x}",
+                header, actual
+            ),
+            DecodeError::UnknownMessage { kind } => {
+                write!(f, "unknown message kind {:#04x}", kind)
+            }
+            DecodeError::Payload { kind, error } => write!(
+                f,
+                "failed to deserialize payload of message kind {:#04x}: {}",
+                kind, error
+            ),
+        }
+    }
+}
+
+pub fn encode(packet: &Packet) -> Result<Vec<u8>, EncodeError> {
+    let kind = packet.message.kind();
+    let payload = packet
+        .message
+        .serialize_payload()
+        .map_err(|err| EncodeError::Payload {
+            kind,
+            error: err.to_string(),
+        })?;
+    if HEADER_LEN + payload.len() > MAX_PACKET_LEN {
+        return Err(EncodeError::TooLong {
+            kind,
+            len: HEADER_LEN + payload.len(),
+        });
+    }
+
+    let mut data = Vec::with_capacity(HEADER_LEN + payload.len());
+    data.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    data.extend_from_slice(&packet.build_id.to_le_bytes());
+    data.extend_from_slice(&packet.session_id.to_le_bytes());
+    data.push(kind);
+    data.extend_from_slice(&(payload.len() as u16).to_le_bytes());
+    data.extend_from_slice(&checksum(&data, &payload).to_le_bytes());
+    data.extend_from_slice(&payload);
+    Ok(data)
+}
+
+pub fn decode(data: &[u8]) -> Result<Packet, DecodeError> {
+    if data.len() < HEADER_LEN {
+        return Err(DecodeError::TooShort { len: data.len() });
+    }
+
+    let version = u16::from_le_bytes([data[0], data[1]]);
+    if version != PROTOCOL_VERSION {
+        return Err(DecodeError::ProtocolVersion { version });
+    }
+
+    let build_id = u32::from_le_bytes(data[2..6].try_into().unwrap());
+    let session_id = u64::from_le_bytes(data[6..14].try_into().unwrap());
+    let kind = data[14];
+
+    let payload = &data[HEADER_LEN..];
+    let header_len = u16::from_le_bytes([data[15], data[16]]) as usize;
+    if header_len != payload.len() {
+        return Err(DecodeError::Length {
+            header: header_len,
+            actual: payload.len(),
+        });
+    }
+
+    let header_checksum = u32::from_le_bytes(data[17..21].try_into().unwrap());
+    let actual_checksum = checksum(&data[..17], payload);
+    if header_checksum != actual_checksum {
+        return Err(DecodeError::Checksum {
+            header: header_checksum,
+            actual: actual_checksum,
+        });
+    }
+
+    Ok(Packet {
+        build_id,
+        session_id,
+        message: Message::deserialize_payload(kind, payload)?,
+    })
+}
+
+fn checksum(header: &[u8], payload: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(header);
+    hasher.update(payload);
+    hasher.finalize()
+}
+
+/// Sends the encoded packets between two ChannelTransport, one packet per step, returning the data received in the order it arrived
+#[cfg(test)]
+fn in_memory_transport(packets: &[Packet], jitter: usize, loss: f64) -> Vec<Vec<u8>> {
+    use super::{ChannelNetwork, Transport};
+
+    let network = ChannelNetwork::new(1, jitter, loss, 0);
+    let sender_address = "10.0.0.1:8413".parse().unwrap();
+    let receiver_address = "10.0.0.2:8413".parse().unwrap();
+    let sender = ChannelNetwork::transport(&network, sender_address);
+    let receiver = ChannelNetwork::transport(&network, receiver_address);
+
+    let mut received = vec![];
+    let mut buf = [0; MAX_PACKET_LEN];
+    for step in 0..packets.len() + jitter + 2 {
+        if let Some(packet) = packets.get(step) {
+            sender
+                .send_to(&encode(packet).unwrap(), receiver_address)
+                .unwrap();
+        }
+        network.lock().unwrap().step();
+        while let Some((len, from)) = receiver.recv_from(&mut buf) {
+            assert_eq!(from, sender_address);
+            received.push(buf[..len].to_vec());
+        }
+    }
+    received
+}
+
+#[test]
+fn codec_round_trip() {
+    use crate::input::state::ControllerInput;
+
+    let sent = vec![
+        Packet {
+            build_id: build_id("test"),
+            session_id: 0,
+            message: Message::PingRequest(7),
+        },
+        Packet {
+            build_id: build_id("test"),
+            session_id: 0x0123_4567_89AB_CDEF,
+            message: Message::InputConfirm(InputConfirm {
+                first_frame: 3,
+                inputs: vec![vec![ControllerInput::default(); 2]; 4],
+                ack: 2,
+                frame: 5,
+            }),
+        },
+        Packet {
+            build_id: build_id("test"),
+            session_id: 42,
+            message: Message::Disconnect,
+        },
+    ];
+
+    let received = in_memory_transport(&sent, 0, 0.0);
+    assert_eq!(received.len(), sent.len());
+    for (sent, received) in sent.iter().zip(received) {
+        let received = decode(&received).unwrap();
+        assert_eq!(received.build_id, sent.build_id);
+        assert_eq!(received.session_id, sent.session_id);
+        assert_eq!(received.message.kind(), sent.message.kind());
+        assert_eq!(
+            received.message.serialize_payload().unwrap(),
+            sent.message.serialize_payload().unwrap()
+        );
+    }
+}
+
+#[test]
+fn codec_malformed_packets() {
+    let packet = Packet {
+        build_id: build_id("test"),
+        session_id: 1,
+        message: Message::PingResponse(200),
+    };
+    let data = in_memory_transport(&[packet], 0, 0.0).remove(0);
+
+    assert_eq!(
+        decode(&data[..HEADER_LEN - 1]).err(),
+        Some(DecodeError::TooShort {
+            len: HEADER_LEN - 1
+        })
+    );
+
+    let mut wrong_version = data.clone();
+    wrong_version[0] = wrong_version[0].wrapping_add(1);
+    assert_eq!(
+        decode(&wrong_version).err(),
+        Some(DecodeError::ProtocolVersion {
+            version: PROTOCOL_VERSION.wrapping_add(1)
+        })
+    );
+
+    let mut truncated = data.clone();
+    truncated.pop();
+    assert_eq!(
+        decode(&truncated).err(),
+        Some(DecodeError::Length {
+            header: 1,
+            actual: 0
+        })
+    );
+
+    let mut corrupted = data.clone();
+    corrupted[HEADER_LEN] = 201;
+    assert!(matches!(
+        decode(&corrupted).err(),
+        Some(DecodeError::Checksum { .. })
+    ));
+
+    let mut unknown = data;
+    unknown[14] = 0x70;
+    let checksum = checksum(&unknown[..17], &unknown[HEADER_LEN..]);
+    unknown[17..21].copy_from_slice(&checksum.to_le_bytes());
+    assert_eq!(
+        decode(&unknown).err(),
+        Some(DecodeError::UnknownMessage { kind: 0x70 })
+    );
+}
+
+#[test]
+fn codec_over_unreliable_transport() {
+    let sent: Vec<Packet> = (0..100)
+        .map(|i| Packet {
+            build_id: build_id("test"),
+            session_id: i,
+            message: Message::PingRequest(i as u8),
+        })
+        .collect();
+    let receive = |jitter, loss| -> Vec<u64> {
+        in_memory_transport(&sent, jitter, loss)
+            .iter()
+            .map(|data| {
+                let packet = decode(data).unwrap();
+                assert_eq!(
+                    packet.message.serialize_payload().unwrap(),
+                    vec![packet.session_id as u8]
+                );
+                packet.session_id
+            })
+            .collect()
+    };
+    let all: Vec<u64> = (0..100).collect();
+
+    // every packet arrives exactly once, but not in the order it was sent
+    let mut received = receive(10, 0.0);
+    assert_ne!(received, all);
+    received.sort_unstable();
+    assert_eq!(received, all);
+
+    // some packets are dropped, the rest arrive intact and only once
+    let mut received = receive(10, 0.5);
+    let len = received.len();
+    assert!(len > 0 && len < all.len());
+    received.sort_unstable();
+    received.dedup();
+    assert_eq!(received.len(), len);
+
+    assert!(receive(0, 1.0).is_empty());
+}
+
+#[test]
+fn codec_rejects_oversized_packets() {
+    use crate::input::state::ControllerInput;
+
+    let packet = Packet {
+        build_id: build_id("test"),
+        session_id: 0,
+        message: Message::InputConfirm(InputConfirm {
+            first_frame: 1,
+            inputs: vec![vec![ControllerInput::default(); 4]; 100],
+            ack: 0,
+            frame: 0,
+        }),
+    };
+    assert!(matches!(
+        encode(&packet),
+        Err(EncodeError::TooLong { kind: 0x05, .. })
+    ));
+}
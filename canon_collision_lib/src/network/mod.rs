@@ -0,0 +1,1318 @@
+pub mod codec;
+mod transport;
+
+pub use transport::{ChannelNetwork, ChannelTransport, Transport, UdpTransport, DEFAULT_PORT};
+
+use crate::config::Config;
+use crate::files::build_version;
+use codec::{Message, Packet};
+use rand;
+use rand::Rng;
+use serde::Serialize;
+use treeflection::{Node, NodeRunner};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Read;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::str;
+use std::time::{Duration, Instant};
+
+use crate::input::state::ControllerInput;
+
+pub struct NetCommandLine {
+    listener: TcpListener,
+}
+
+impl NetCommandLine {
+    pub fn new() -> NetCommandLine {
+        // can fail when using scripts to quickly relaunch
+        let listener = match TcpListener::bind("127.0.0.1:1613") {
+            Ok(listener) => listener,
+            Err(_) => {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                TcpListener::bind("127.0.0.1:1613").unwrap()
+            }
+        };
+
+        listener.set_nonblocking(true).unwrap();
+
+        NetCommandLine { listener }
+    }
+
+    pub fn step<T>(&mut self, root_node: &mut T)
+    where
+        T: Node,
+    {
+        let mut buf = [0; 1024];
+        if let Ok((mut stream, _)) = self.listener.accept() {
+            match stream.read(&mut buf) {
+                Ok(amt) => {
+                    if amt > 1 {
+                        if let Ok(string) = str::from_utf8(&buf[1..amt]) {
+                            if buf[0] == 0x43 {
+                                // 'C'
+                                let out = NetCommandLine::run_inner(string, root_node);
+                                if let Err(e) = stream.write(out.as_bytes()) {
+                                    println!("command send failed {}", e);
+                                }
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("command receive failed {}", e);
+                }
+            }
+        }
+    }
+
+    fn run_inner<T>(command: &str, package: &mut T) -> String
+    where
+        T: Node,
+    {
+        match NodeRunner::new(command) {
+            Ok(runner) => package.node_step(runner),
+            Err(msg) => msg,
+        }
+    }
+}
+
+/// The matchmaking server used when none is configured
+pub const DEFAULT_MATCHMAKING_HOST: &str = "matchmaking.canoncollision.com:8413";
+
+/// The maximum number of frames the local machine can run ahead of the last confirmed input.
+/// Remote inputs for these frames are predicted by repeating the last confirmed input.
+pub const MAX_ROLLBACK_FRAMES: usize = 8;
+
+/// The maximum number of frames local inputs can be delayed by, whether configured or chosen from the ping.
+pub const MAX_INPUT_DELAY: usize = 6;
+
+/// The maximum number of frames of unacknowledged inputs that are resent in a single packet.
+/// Peers can be at most MAX_ROLLBACK_FRAMES ahead of each other and inputs are sent up to MAX_INPUT_DELAY frames early,
+/// so this covers every frame that can be unacknowledged while acks are arriving.
+/// Fewer frames are sent when there are too many controllers to fit this many frames in a packet, see max_input_frames_per_packet.
+const MAX_INPUT_FRAMES_PER_PACKET: usize = MAX_ROLLBACK_FRAMES * 2 + MAX_INPUT_DELAY + 2;
+
+/// The bincode serialized length of an InputConfirm or SpectatorInputs excluding the inputs of each frame.
+/// InputConfirm: first_frame, ack, frame and the length of inputs
+/// SpectatorInputs: seed, num_players, first_frame and the length of inputs
+const INPUTS_MESSAGE_OVERHEAD: usize = 8 * 4;
+
+const FRAME_DURATION_MS: f64 = 1000.0 / 60.0;
+
+/// While the local machine is ahead of its peers, each frame is lengthened by this fraction of a frame for every frame it is ahead.
+/// The faster machine gradually slows down to let peers catch up without any frame visibly freezing.
+const TIME_SYNC_SLOWDOWN: f32 = 0.05;
+
+/// Frames are never lengthened by more than this fraction of a frame, so slowing down is barely noticeable.
+const MAX_TIME_SYNC_SLOWDOWN: f32 = 0.25;
+
+/// How much each new frame advantage estimate affects the smoothed frame advantage, between 0.0 and 1.0
+const FRAME_ADVANTAGE_SMOOTHING: f32 = 0.1;
+
+/// The number of frames of game state hashes sent to peers in each StateHashes message.
+const STATE_HASH_INTERVAL: usize = 60;
+
+/// The maximum number of frames of inputs sent to a spectator in a single packet.
+/// Sent every frame so a spectator that joins late quickly receives the entire session.
+/// Fewer frames are sent when there are too many players and controllers to fit this many frames in a packet.
+const SPECTATOR_FRAMES_PER_PACKET: usize = 8;
+
+/// How long local game state hashes are kept for comparison with peers hashes that have not yet arrived.
+const STATE_HASH_HISTORY: usize = 600;
+
+pub struct Netplay {
+    // structure: peers Vec<frames Vec<controllers Vec<ControllerInput>>>
+    // frame 0 has index 2
+    pub confirmed_inputs: Vec<Vec<Vec<ControllerInput>>>,
+    /// The earliest frame that was stepped with a mispredicted input, since the last time the game advanced.
+    rollback_frame: Option<usize>,
+    /// Decided once at the start of each step so that everything stepped during the frame agrees on whether it is skipped
+    skip_frame: bool,
+    match_making_response: Option<MatchMakingResponse>,
+    peers: Vec<SocketAddr>,
+    seed: u64,
+    transport: Box<dyn Transport>,
+    state: NetplayState,
+    state_frame: usize,
+    /// The session frame that frame 0 of the current game or menu corresponds to, see reset_frame_origin
+    frame_origin: usize,
+    last_received_frame: usize,
+    index: usize,
+    /// The most recent InitConnection received from each peer, kept in the same order as peers
+    init_msgs: Vec<Option<InitConnection>>,
+    /// Random values that collided with another peers value, InitConnection messages using them are from before the peers chose new values
+    rejected_randoms: Vec<u64>,
+    /// structure: Vec<(peer index, ping id)>
+    ping_msgs: Vec<(usize, u8)>,
+    start_request_msgs: Vec<usize>,
+    start_confirm_msgs: Vec<usize>,
+    /// Received inputs that are not yet confirmed because an earlier frame is missing
+    /// structure: peers Vec<frame BTreeMap<controllers Vec<ControllerInput>>>
+    running_msgs: Vec<BTreeMap<usize, Vec<ControllerInput>>>,
+    /// Local inputs that have not yet been acknowledged by every peer
+    /// structure: frames VecDeque<controllers Vec<ControllerInput>>
+    unacked_inputs: VecDeque<Vec<ControllerInput>>,
+    /// The frame of the first element in unacked_inputs
+    unacked_first_frame: usize,
+    /// The highest frame for which each peer has received every local input
+    peer_acks: Vec<usize>,
+    /// Identifies packets sent by this build, packets from other builds are rejected
+    build_id: u32,
+    /// Identifies packets sent within the current session, 0 until the session is established
+    session_id: u64,
+    /// Set when a packet from a peer running a different build is rejected
+    rejected_build: bool,
+    /// Hashes of the local game state for each confirmed frame
+    /// structure: frame BTreeMap<hash>
+    local_state_hashes: BTreeMap<usize, u32>,
+    /// Hashes of the peers game state that arrived before the local hash of the same frame was calculated
+    /// structure: peers Vec<frame BTreeMap<hash>>
+    remote_state_hashes: Vec<BTreeMap<usize, u32>>,
+    /// Local state hashes for frames before this have been sent to peers
+    sent_state_hash_frame: usize,
+    /// The earliest frame at which the local game state differed from a peers game state
+    desync_frame: Option<usize>,
+    /// Every local input sent during the session, kept so that it can be sent to spectators
+    /// structure: frames Vec<controllers Vec<ControllerInput>>
+    local_inputs: Vec<Vec<ControllerInput>>,
+    /// Machines watching the session from the local machine
+    spectators: Vec<Spectator>,
+    /// When spectating, the number of frames the simulation is kept behind the received inputs
+    spectator_delay: Option<usize>,
+    /// The number of frames local inputs are delayed by, chosen when the session starts running
+    input_delay: usize,
+    /// The configured input delay, None chooses the input delay from the ping
+    input_delay_setting: Option<usize>,
+    /// Sessions with an average round trip ping above this, in milliseconds, are disconnected
+    max_ping: f64,
+    /// The number of frames it takes a packet to reach the slowest peer, measured during the PingTest
+    latency_frames: usize,
+    /// The most recent frame each peer was on when it sent inputs
+    remote_frames: Vec<usize>,
+    /// The smoothed estimate of how many frames the local machine is ahead of the furthest behind peer
+    frame_advantage: f32,
+}
+
+impl Netplay {
+    pub fn new(transport: Box<dyn Transport>) -> Netplay {
+        Netplay {
+            state: NetplayState::Offline,
+            state_frame: 0,
+            frame_origin: 0,
+            last_received_frame: 0,
+            confirmed_inputs: vec![],
+            rollback_frame: None,
+            skip_frame: false,
+            match_making_response: None,
+            peers: vec![],
+            seed: 0,
+            index: 0,
+            init_msgs: vec![],
+            rejected_randoms: vec![],
+            ping_msgs: vec![],
+            start_request_msgs: vec![],
+            start_confirm_msgs: vec![],
+            running_msgs: vec![],
+            unacked_inputs: VecDeque::new(),
+            unacked_first_frame: 0,
+            peer_acks: vec![],
+            build_id: codec::build_id(&build_version()),
+            session_id: 0,
+            rejected_build: false,
+            local_state_hashes: BTreeMap::new(),
+            remote_state_hashes: vec![],
+            sent_state_hash_frame: 0,
+            desync_frame: None,
+            local_inputs: vec![],
+            spectators: vec![],
+            spectator_delay: None,
+            input_delay: 0,
+            input_delay_setting: None,
+            max_ping: 100.0,
+            latency_frames: 0,
+            remote_frames: vec![],
+            frame_advantage: 0.0,
+            transport,
+        }
+    }
+
+    /// Apply the netplay settings from the config, takes effect from the next session
+    pub fn set_config(&mut self, config: &Config) {
+        self.input_delay_setting = config.netplay_input_delay;
+        self.max_ping = config.netplay_max_ping as f64;
+    }
+
+    /// Call this once every frame
+    pub fn step(&mut self) {
+        self.skip_frame = self.too_far_ahead();
+        if !self.skip_frame {
+            self.state_frame += 1;
+            // The rollback has been handled by the previous frame
+            self.rollback_frame = None;
+        }
+
+        // receive messages
+        loop {
+            let mut buf = [0; codec::MAX_PACKET_LEN];
+            if let Some((len, addr)) = self.transport.recv_from(&mut buf) {
+                match codec::decode(&buf[..len]) {
+                    Ok(packet) => self.receive_packet(addr, packet),
+                    Err(err) => warn!("Discarded netplay packet from {}: {}", addr, err),
+                }
+            } else {
+                break;
+            }
+        }
+
+        if !self.peers.is_empty() && self.state_frame - self.last_received_frame > 600 {
+            if self.rejected_build {
+                self.disconnect_with_reason("Build versions did not match, ensure everyone is using the same Canon Collision build.");
+            } else {
+                self.disconnect_with_reason(
+                    "Connection timed out: no packets received in the last 10 seconds",
+                );
+            }
+        }
+
+        // process messages
+        match self.state.clone() {
+            NetplayState::Offline => {}
+            NetplayState::Disconnected { .. } => {}
+            NetplayState::MatchMaking { request, host } => {
+                if self.state_frame % 600 == 1 {
+                    // Send a request every 10 seconds
+                    let message = Message::MatchMakingRequest(request.clone());
+                    let sent = host
+                        .to_socket_addrs()
+                        .ok()
+                        .and_then(|mut addresses| addresses.next())
+                        .map_or(false, |address| self.send_to(&address, message).is_ok());
+                    if !sent {
+                        self.disconnect_with_reason(&format!("{} is inaccessible", host));
+                    }
+                }
+                if let &Some(ref response) = &self.match_making_response {
+                    for peer in response.addresses.iter() {
+                        if !self.peers.contains(peer) {
+                            self.add_peer(*peer);
+                        }
+                    }
+                }
+                if self.peers.len() as u8 + 1 == request.num_players {
+                    self.set_state(NetplayState::InitConnection(InitConnection {
+                        random: rand::thread_rng().gen::<u64>(),
+                    }));
+                }
+            }
+            NetplayState::InitConnection(local) => {
+                // send init
+                self.broadcast(Message::Init(local.clone()), "init");
+
+                // receive init
+                if self.init_msgs.iter().all(|x| x.is_some()) {
+                    let remote_inits: Vec<InitConnection> =
+                        self.init_msgs.iter().flatten().cloned().collect();
+                    let mut randoms: Vec<u64> = remote_inits.iter().map(|x| x.random).collect();
+                    randoms.push(local.random);
+                    randoms.sort_unstable();
+                    if randoms.windows(2).any(|x| x[0] == x[1]) {
+                        // Peers with the same random value would be ordered differently by each peer.
+                        // Every peer sees the same values so they all choose new values, ignoring any old values still arriving.
+                        self.rejected_randoms.extend(randoms);
+                        self.init_msgs = vec![None; self.peers.len()];
+                        self.set_state(NetplayState::InitConnection(InitConnection {
+                            random: rand::thread_rng().gen::<u64>(),
+                        }));
+                    } else {
+                        self.order_peers(&local, &remote_inits);
+                        self.set_state(NetplayState::PingTest {
+                            local_init: local.clone(),
+                            pings: vec![[Ping::default(); 255]; self.peers.len()],
+                        });
+                    }
+                }
+            }
+            NetplayState::PingTest {
+                local_init,
+                mut pings,
+            } => {
+                // if we havnt received a ping from every peer yet then resend init message
+                if pings
+                    .iter()
+                    .any(|peer_pings| peer_pings.iter().all(|x| x.time_received.is_none()))
+                {
+                    self.broadcast(Message::Init(local_init.clone()), "init2");
+                }
+
+                // record the time_received of received pings
+                for (peer, ping_msg) in self.ping_msgs.drain(..) {
+                    let ping = &mut pings[peer][ping_msg as usize];
+                    if ping.time_received.is_none() {
+                        ping.time_received = Some(Instant::now());
+                    }
+                }
+
+                // request a ping from peers and record the time_sent
+                if let Some(next_ping) = pings
+                    .get(0)
+                    .and_then(|x| x.iter().position(|x| x.time_sent.is_none()))
+                {
+                    self.broadcast(Message::PingRequest(next_ping as u8), "ping");
+                    for peer_pings in pings.iter_mut() {
+                        peer_pings[next_ping].time_sent = Some(Instant::now());
+                    }
+                    self.state = NetplayState::PingTest { local_init, pings };
+                } else {
+                    // The session is only as good as the worst connection between the local machine and a peer
+                    let samples = 225;
+                    let mut ping_total = Duration::from_secs(0);
+                    for peer_pings in pings.iter() {
+                        let mut peer_ping_total = Duration::from_secs(0);
+                        for ping in peer_pings.iter().take(samples) {
+                            // skip the last 30 as we dont want the most recent packets showing up as dropped.
+                            if let (Some(time_sent), Some(time_received)) =
+                                (ping.time_sent, ping.time_received)
+                            {
+                                peer_ping_total += time_received.duration_since(time_sent);
+                            } else {
+                                peer_ping_total += Duration::from_millis(200); // punish for dropping packet
+                            }
+                        }
+                        ping_total = ping_total.max(peer_ping_total);
+                    }
+
+                    let ping_total_ms = ping_total.as_secs() as f64 * 1000.0
+                        + ping_total.subsec_nanos() as f64 / 1_000_000.0;
+                    let ping_avg = ping_total_ms / samples as f64;
+                    if ping_avg > self.max_ping {
+                        self.disconnect_with_reason(
+                            format!(
+                                "The ping was '{:.0}ms' which was above the limit of '{:.0}ms'",
+                                ping_avg, self.max_ping
+                            )
+                            .as_ref(),
+                        );
+                    } else {
+                        // An input takes half the round trip to reach a peer, delaying local inputs by that long means peers rarely need to predict them.
+                        self.latency_frames = (ping_avg / 2.0 / FRAME_DURATION_MS).round() as usize;
+                        self.input_delay = self
+                            .input_delay_setting
+                            .unwrap_or(self.latency_frames)
+                            .min(MAX_INPUT_DELAY);
+                        self.set_state(NetplayState::Running);
+                        // TODO: Need to force input reset all history at this point
+                    }
+                }
+            }
+            NetplayState::SpectatorWait | NetplayState::Running if self.is_spectating() => {
+                // Doubles as an ack so the host knows which inputs to send next
+                let request = SpectatorRequest {
+                    ack: self.confirmed_frames(),
+                };
+                self.broadcast(Message::SpectatorRequest(request), "spectator request");
+            }
+            NetplayState::SpectatorWait => {}
+            NetplayState::Running => {
+                for peer in 0..self.peers.len() {
+                    self.confirm_peer_inputs(peer);
+                }
+                self.update_frame_advantage();
+
+                // No new inputs will be sent this frame, but peers may still be waiting on lost inputs.
+                if self.skip_frame() {
+                    self.send_unacked_inputs();
+                }
+
+                self.send_spectator_inputs();
+            }
+        }
+        debug!("state: {}", self.state.to_string());
+        debug!("number_of_peers: {}", self.number_of_peers());
+        debug!("local_index: {}", self.local_index());
+        debug!("frame: {}", self.frame());
+        debug!("frames_to_step: {}", self.frames_to_step());
+        debug!("skip_frame: {}", self.skip_frame());
+    }
+
+    fn receive_packet(&mut self, addr: SocketAddr, packet: Packet) {
+        if packet.build_id != self.build_id {
+            debug!("Rejected netplay packet from {}: different build", addr);
+            self.rejected_build = true;
+            return;
+        }
+        self.last_received_frame = self.state_frame;
+
+        let peer = self.peers.iter().position(|x| x == &addr);
+        // Messages that are only meaningful within a session are rejected if they were sent in a different session
+        let same_session = packet.session_id == self.session_id;
+        match (packet.message, peer) {
+            (Message::MatchMakingResponse(response), _) => {
+                self.match_making_response = Some(response);
+            }
+            (Message::Init(init), Some(peer)) => {
+                if !self.rejected_randoms.contains(&init.random) {
+                    self.init_msgs[peer] = Some(init);
+                }
+            }
+            (Message::PingRequest(ping), Some(_)) => {
+                // Respond within the requesting peers session as we may not have established ours yet
+                let packet = Packet {
+                    build_id: self.build_id,
+                    session_id: packet.session_id,
+                    message: Message::PingResponse(ping),
+                };
+                match codec::encode(&packet) {
+                    Ok(data) => {
+                        self.transport.send_to(&data, addr).ok();
+                    }
+                    Err(err) => error!("Dropped netplay packet to {}: {}", addr, err),
+                }
+            }
+            (Message::PingResponse(ping), Some(peer)) if same_session => {
+                self.ping_msgs.push((peer, ping));
+            }
+            (Message::InputConfirm(input_confirm), Some(peer)) if same_session => {
+                self.receive_input_confirm(peer, input_confirm);
+            }
+            (Message::StateHashes(state_hashes), Some(peer)) if same_session => {
+                for (i, hash) in state_hashes.hashes.into_iter().enumerate() {
+                    let frame = state_hashes.first_frame + i;
+                    let latest_local_frame = self.local_state_hashes.keys().next_back().cloned();
+                    if let Some(local_hash) = self.local_state_hashes.get(&frame).cloned() {
+                        self.compare_state_hashes(frame, local_hash, hash);
+                    } else if latest_local_frame.map_or(true, |latest| frame > latest) {
+                        // compare once the local hash is calculated
+                        self.remote_state_hashes[peer].insert(frame, hash);
+                    }
+                }
+            }
+            (Message::SpectatorRequest(request), None) => {
+                if let (NetplayState::Running, false) = (&self.state, self.is_spectating()) {
+                    if let Some(spectator) = self.spectators.iter_mut().find(|x| x.address == addr)
+                    {
+                        spectator.ack = spectator.ack.max(request.ack);
+                    } else {
+                        info!("Spectator {} joined", addr);
+                        self.spectators.push(Spectator {
+                            address: addr,
+                            ack: request.ack,
+                        });
+                    }
+                }
+            }
+            (Message::SpectatorInputs(spectator_inputs), Some(_)) if self.is_spectating() => {
+                // The spectator joins the session of the host it is watching
+                if self.session_id == 0 {
+                    self.session_id = packet.session_id;
+                }
+                if packet.session_id == self.session_id {
+                    self.receive_spectator_inputs(spectator_inputs);
+                }
+            }
+            (Message::Disconnect, None) => {
+                self.spectators.retain(|x| x.address != addr);
+            }
+            // A peer that has not established the session yet can still disconnect from it
+            (Message::Disconnect, Some(_)) if same_session || packet.session_id == 0 => {
+                self.disconnect_with_reason("Peer disconnected");
+            }
+            _ => {
+                debug!(
+                    "Rejected netplay packet from {}: not from a peer in session {}",
+                    addr, self.session_id
+                );
+            }
+        }
+    }
+
+    fn receive_spectator_inputs(&mut self, spectator_inputs: SpectatorInputs) {
+        if let NetplayState::SpectatorWait = self.state {
+            self.seed = spectator_inputs.seed;
+            self.confirmed_inputs = vec![vec![]; spectator_inputs.num_players];
+            self.set_state(NetplayState::Running);
+        }
+
+        for (i, players) in spectator_inputs.inputs.into_iter().enumerate() {
+            // frames start at 1, the same as InputConfirm
+            let frame = spectator_inputs.first_frame + i;
+            if frame == self.confirmed_frames() + 1 && players.len() == self.confirmed_inputs.len()
+            {
+                for (player, inputs) in players.into_iter().enumerate() {
+                    self.confirmed_inputs[player].push(inputs);
+                }
+            }
+        }
+    }
+
+    /// Send each spectator the next inputs that every player has confirmed.
+    fn send_spectator_inputs(&mut self) {
+        let confirmed_frames = self.confirmed_frames().min(self.local_inputs.len());
+        for spectator in &self.spectators {
+            let first = spectator.ack;
+            let max_frames = if first < confirmed_frames {
+                let frame_len =
+                    bincode::serialized_size(&self.players_inputs(first)).unwrap() as usize;
+                max_frames_per_packet(frame_len, SPECTATOR_FRAMES_PER_PACKET)
+            } else {
+                0
+            };
+            let last = confirmed_frames.min(first + max_frames);
+            let spectator_inputs = SpectatorInputs {
+                seed: self.seed,
+                num_players: self.number_of_peers(),
+                first_frame: first + 1,
+                inputs: (first..last)
+                    .map(|frame| self.players_inputs(frame))
+                    .collect(),
+            };
+            self.send_to(
+                &spectator.address,
+                Message::SpectatorInputs(spectator_inputs),
+            )
+            .ok();
+        }
+    }
+
+    /// Returns the inputs of every player, in player order, at the specified index into confirmed_inputs
+    /// structure: players Vec<controllers Vec<ControllerInput>>
+    fn players_inputs(&self, frame: usize) -> Vec<Vec<ControllerInput>> {
+        let mut players: Vec<Vec<ControllerInput>> = self
+            .confirmed_inputs
+            .iter()
+            .map(|x| x[frame].clone())
+            .collect();
+        players.insert(self.index, self.local_inputs[frame].clone());
+        players
+    }
+
+    fn receive_input_confirm(&mut self, peer: usize, input_confirm: InputConfirm) {
+        self.remote_frames[peer] = self.remote_frames[peer].max(input_confirm.frame);
+
+        let confirmed_len = self.confirmed_inputs[peer].len();
+        for (i, inputs) in input_confirm.inputs.into_iter().enumerate() {
+            let frame = input_confirm.first_frame + i;
+            if frame > confirmed_len {
+                self.running_msgs[peer].insert(frame, inputs);
+            }
+        }
+
+        // discard local inputs that every peer has received
+        self.peer_acks[peer] = self.peer_acks[peer].max(input_confirm.ack);
+        let min_ack = self.peer_acks.iter().cloned().min().unwrap_or(0);
+        while self.unacked_first_frame <= min_ack && self.unacked_inputs.pop_front().is_some() {
+            self.unacked_first_frame += 1;
+        }
+    }
+
+    /// Move any inputs received from the specified peer that continue on from its confirmed inputs into its confirmed inputs.
+    fn confirm_peer_inputs(&mut self, peer: usize) {
+        // Any frames already stepped past the last confirmed input were stepped with this input repeated.
+        let prediction = self.confirmed_inputs[peer].last().cloned();
+
+        loop {
+            // frames start at 1 because they are taken from the peers state_frame which is incremented before any logic is run
+            let frame = self.confirmed_inputs[peer].len() + 1;
+            let inputs = match self.running_msgs[peer].remove(&frame) {
+                Some(inputs) => inputs,
+                None => break,
+            };
+
+            let mispredicted = prediction.as_ref().map(|x| x != &inputs).unwrap_or(true);
+            if mispredicted && frame < self.state_frame {
+                self.rollback_frame = Some(self.rollback_frame.map_or(frame, |x| x.min(frame)));
+            }
+
+            self.confirmed_inputs[peer].push(inputs);
+        }
+    }
+
+    /// The frame a peer sent its inputs on is received latency_frames later, so by now the peer has stepped that many more frames.
+    /// The estimate is smoothed so that jitter in packet arrival does not cause the frame duration to fluctuate.
+    fn update_frame_advantage(&mut self) {
+        let local_frame = self.state_frame as f32;
+        let latency_frames = self.latency_frames;
+        let advantage = self
+            .remote_frames
+            .iter()
+            .map(|remote_frame| local_frame - (remote_frame + latency_frames) as f32)
+            .fold(f32::MIN, f32::max);
+        if !self.remote_frames.is_empty() {
+            self.frame_advantage += (advantage - self.frame_advantage) * FRAME_ADVANTAGE_SMOOTHING;
+        }
+    }
+
+    /// Record the hash of the local game state after stepping the specified session frame.
+    /// Must only be called for frames that are confirmed, in order, so that the hash can never change due to a rollback.
+    pub fn add_state_hash(&mut self, frame: usize, hash: u32) {
+        // Spectators cant desync the session, they can only be given the wrong inputs
+        if self.is_spectating() {
+            return;
+        }
+
+        for peer in 0..self.remote_state_hashes.len() {
+            if let Some(remote_hash) = self.remote_state_hashes[peer].remove(&frame) {
+                self.compare_state_hashes(frame, hash, remote_hash);
+            }
+        }
+
+        self.local_state_hashes.insert(frame, hash);
+        if frame >= STATE_HASH_HISTORY {
+            self.local_state_hashes = self
+                .local_state_hashes
+                .split_off(&(frame - STATE_HASH_HISTORY));
+        }
+
+        // Periodically send the accumulated hashes to peers
+        if frame + 1 >= self.sent_state_hash_frame + STATE_HASH_INTERVAL {
+            let state_hashes = StateHashes {
+                first_frame: self.sent_state_hash_frame,
+                hashes: self
+                    .local_state_hashes
+                    .range(self.sent_state_hash_frame..=frame)
+                    .map(|(_, hash)| *hash)
+                    .collect(),
+            };
+            self.broadcast(Message::StateHashes(state_hashes), "state hashes");
+            self.sent_state_hash_frame = frame + 1;
+        }
+    }
+
+    fn compare_state_hashes(&mut self, frame: usize, local_hash: u32, remote_hash: u32) {
+        if local_hash != remote_hash {
+            self.desync_frame = Some(self.desync_frame.map_or(frame, |x| x.min(frame)));
+        }
+    }
+
+    /// Returns the earliest session frame at which the local game state differed from a peers game state
+    pub fn desync_frame(&self) -> Option<usize> {
+        self.desync_frame
+    }
+
+    /// Returns the number of frames, starting from frame 0, for which the inputs of every peer are confirmed
+    pub fn confirmed_frames(&self) -> usize {
+        self.confirmed_inputs
+            .iter()
+            .map(|x| x.len())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Every peer sorts all InitConnection messages (including its own) by their random value, this order becomes the player order.
+    /// The random values must all be different, otherwise peers could disagree on the order.
+    /// Peers are then stored in player order (skipping the local machine) so that confirmed_inputs can be merged with the local inputs in player order.
+    /// Peer 0's random value is used as the game seed for all games in the current session.
+    /// Repeating seeds like this shouldnt be noticeable
+    fn order_peers(&mut self, local: &InitConnection, remote_inits: &[InitConnection]) {
+        let mut order: Vec<usize> = (0..self.peers.len()).collect();
+        order.sort_by_key(|i| remote_inits[*i].random);
+
+        self.index = remote_inits
+            .iter()
+            .filter(|x| x.random < local.random)
+            .count();
+        self.seed = remote_inits
+            .iter()
+            .map(|x| x.random)
+            .fold(local.random, u64::min);
+        self.session_id = remote_inits
+            .iter()
+            .map(|x| x.random)
+            .fold(local.random, |acc, x| acc ^ x);
+
+        // Inputs may have already been received from peers that started running before us
+        self.peers = reorder(&self.peers, &order);
+        self.confirmed_inputs = reorder(&self.confirmed_inputs, &order);
+        self.init_msgs = reorder(&self.init_msgs, &order);
+        self.running_msgs = reorder(&self.running_msgs, &order);
+        self.peer_acks = reorder(&self.peer_acks, &order);
+        self.remote_state_hashes = reorder(&self.remote_state_hashes, &order);
+        self.remote_frames = reorder(&self.remote_frames, &order);
+    }
+
+    pub fn state(&self) -> NetplayState {
+        self.state.clone()
+    }
+
+    /// Returns the index of the local machine
+    pub fn local_index(&self) -> usize {
+        match &self.state {
+            NetplayState::Running { .. } => self.index,
+            _ => 0,
+        }
+    }
+
+    /// Returns the total number of peers including the local machine
+    pub fn number_of_peers(&self) -> usize {
+        if self.is_spectating() {
+            self.confirmed_inputs.len()
+        } else {
+            self.peers.len() + 1
+        }
+    }
+
+    /// Returns true if the local machine is watching a session it has no players in
+    pub fn is_spectating(&self) -> bool {
+        self.spectator_delay.is_some()
+    }
+
+    /// Returns the number of frames that need to be stepped/restepped including the current frame
+    /// When a remote input was mispredicted this reaches back to the first mispredicted frame.
+    pub fn frames_to_step(&self) -> usize {
+        match (&self.state, self.rollback_frame) {
+            (NetplayState::Running, Some(rollback_frame)) => {
+                self.state_frame.saturating_sub(rollback_frame).max(1)
+            }
+            _ => 1,
+        }
+    }
+
+    pub fn frame(&self) -> usize {
+        match &self.state {
+            NetplayState::Running => self.state_frame,
+            _ => 0,
+        }
+    }
+
+    /// Call this when the game or menu starts counting its frames from 0 again.
+    /// Inputs and state hashes are indexed by session frame, which keeps counting across every game and menu in the session,
+    /// so the current frame becomes frame 0 of the game or menu.
+    pub fn reset_frame_origin(&mut self) {
+        self.frame_origin = self.frame();
+    }
+
+    /// Returns the session frame that frame 0 of the current game or menu corresponds to.
+    /// Add this to a game or menu frame to get its session frame.
+    pub fn frame_origin(&self) -> usize {
+        self.frame_origin
+    }
+
+    /// Returns true if the local machine should do nothing for a frame so that peers can catch up.
+    /// This only occurs when the local machine is further ahead than we are willing to predict,
+    /// otherwise the faster machine is gradually slowed down by frame_duration.
+    pub fn skip_frame(&self) -> bool {
+        self.skip_frame
+    }
+
+    /// Returns how long the current frame should take.
+    /// Frames are lengthened in proportion to how far the local machine is ahead of its peers, so that they can catch up.
+    pub fn frame_duration(&self) -> Duration {
+        let frame_duration = Duration::from_secs(1) / 60;
+        match (&self.state, self.spectator_delay) {
+            (NetplayState::Running, None) => {
+                let slowdown = (self.frame_advantage * TIME_SYNC_SLOWDOWN)
+                    .max(0.0)
+                    .min(MAX_TIME_SYNC_SLOWDOWN);
+                frame_duration.mul_f32(1.0 + slowdown)
+            }
+            _ => frame_duration,
+        }
+    }
+
+    /// Returns the number of frames local inputs are delayed by in the current session
+    pub fn input_delay(&self) -> usize {
+        self.input_delay
+    }
+
+    /// Returns every local input sent during the session, including the neutral inputs filling the input delay at the start of the session.
+    /// Returns None when the local inputs are not sent to peers.
+    pub fn local_inputs(&self) -> Option<&[Vec<ControllerInput>]> {
+        match &self.state {
+            NetplayState::Running if !self.is_spectating() => Some(&self.local_inputs),
+            _ => None,
+        }
+    }
+
+    fn too_far_ahead(&self) -> bool {
+        let input_frames = self
+            .confirmed_inputs
+            .iter()
+            .map(|x| x.len())
+            .min()
+            .unwrap_or(1);
+        match (&self.state, self.spectator_delay) {
+            // Spectators never predict inputs, the delay gives them a buffer of inputs to keep playing smoothly
+            (NetplayState::Running, Some(delay)) => self.state_frame + 1 + delay > input_frames,
+            (NetplayState::Running, None) => self.state_frame > input_frames + MAX_ROLLBACK_FRAMES,
+            _ => false,
+        }
+    }
+
+    /// Return the seed used for this netplay session
+    pub fn get_seed(&self) -> Option<u64> {
+        match &self.state {
+            NetplayState::Running { .. } => Some(self.seed),
+            _ => None,
+        }
+    }
+
+    fn send_to(&self, address: &SocketAddr, message: Message) -> std::io::Result<()> {
+        let packet = Packet {
+            build_id: self.build_id,
+            session_id: self.session_id,
+            message,
+        };
+        match codec::encode(&packet) {
+            Ok(data) => self.transport.send_to(&data, *address),
+            Err(err) => {
+                // The packet can never be sent, so dont treat it as the peer being inaccessible
+                error!("Dropped netplay packet to {}: {}", address, err);
+                Ok(())
+            }
+        }
+    }
+
+    fn broadcast(&mut self, message: Message, message_name: &str) {
+        let mut fail = false;
+        for peer in self.peers.iter() {
+            if let Err(_) = self.send_to(peer, message.clone()) {
+                fail = true;
+                break;
+            }
+        }
+        if fail {
+            self.disconnect_with_reason(
+                format!("Peer is inaccessible: failed to send {}", message_name).as_ref(),
+            );
+        }
+    }
+
+    fn clear(&mut self) {
+        self.confirmed_inputs.clear();
+        self.index = 0;
+        self.init_msgs.clear();
+        self.rejected_randoms.clear();
+        self.last_received_frame = 0;
+        self.match_making_response = None;
+        self.peers.clear();
+        self.ping_msgs.clear();
+        self.rollback_frame = None;
+        self.skip_frame = false;
+        self.running_msgs.clear();
+        self.unacked_inputs.clear();
+        self.unacked_first_frame = 0;
+        self.peer_acks.clear();
+        self.seed = 0;
+        self.session_id = 0;
+        self.rejected_build = false;
+        self.local_state_hashes.clear();
+        self.remote_state_hashes.clear();
+        self.sent_state_hash_frame = 0;
+        self.desync_frame = None;
+        self.local_inputs.clear();
+        self.spectators.clear();
+        self.spectator_delay = None;
+        self.input_delay = 0;
+        self.latency_frames = 0;
+        self.remote_frames.clear();
+        self.frame_advantage = 0.0;
+        self.start_confirm_msgs.clear();
+        self.start_request_msgs.clear();
+        self.state_frame = 0;
+        self.frame_origin = 0;
+    }
+
+    fn add_peer(&mut self, address: SocketAddr) {
+        self.peers.push(address);
+        self.confirmed_inputs.push(vec![]);
+        self.init_msgs.push(None);
+        self.running_msgs.push(BTreeMap::new());
+        self.peer_acks.push(0);
+        self.remote_state_hashes.push(BTreeMap::new());
+        self.remote_frames.push(0);
+    }
+
+    /// Connect directly to every specified address, all peers need to specify every other peers address.
+    pub fn direct_connect(&mut self, addresses: &[SocketAddr]) {
+        self.clear();
+        for address in addresses {
+            self.add_peer(*address);
+        }
+        self.set_state(NetplayState::InitConnection(InitConnection {
+            random: rand::thread_rng().gen::<u64>(),
+        }));
+    }
+
+    /// Watch the session that the peer at the specified address is playing in.
+    /// The simulation is kept the specified number of frames behind the received inputs so that it can play smoothly.
+    pub fn spectate(&mut self, address: SocketAddr, delay: usize) {
+        self.clear();
+        self.add_peer(address);
+        self.spectator_delay = Some(delay);
+        self.set_state(NetplayState::SpectatorWait);
+    }
+
+    pub fn connect_match_making(&mut self, host: String, region: String, num_players: u8) {
+        self.clear();
+        let request = MatchMakingRequest {
+            build_version: build_version(),
+            region,
+            num_players,
+        };
+        self.set_state(NetplayState::MatchMaking { request, host });
+    }
+
+    /// Returns the address of every peer and spectator
+    fn addresses(&self) -> impl Iterator<Item = &SocketAddr> {
+        self.peers
+            .iter()
+            .chain(self.spectators.iter().map(|x| &x.address))
+    }
+
+    fn set_state(&mut self, state: NetplayState) {
+        self.state = state;
+        self.state_frame = 0;
+        self.frame_origin = 0;
+        self.last_received_frame = 0;
+    }
+
+    pub fn disconnect_with_reason(&mut self, reason: &str) {
+        match &self.state {
+            NetplayState::Offline | NetplayState::Disconnected { .. } => {}
+            _ => {
+                for address in self.addresses() {
+                    self.send_to(address, Message::Disconnect).ok();
+                }
+                self.set_state(NetplayState::Disconnected {
+                    reason: String::from(reason),
+                });
+                self.clear();
+            }
+        }
+    }
+
+    pub fn set_offline(&mut self) {
+        match &self.state {
+            NetplayState::Offline => {}
+            _ => {
+                for address in self.addresses() {
+                    self.send_to(address, Message::Disconnect).ok();
+                }
+                self.set_state(NetplayState::Offline);
+                self.clear();
+            }
+        }
+    }
+
+    pub fn send_controller_inputs(&mut self, inputs: Vec<ControllerInput>) {
+        if self.is_spectating() {
+            return;
+        }
+
+        if let NetplayState::Running = &self.state {
+            // Local inputs take effect input_delay frames after they are read
+            let frame = self.state_frame + self.input_delay;
+            if self.local_inputs.is_empty() {
+                // No inputs were read before the session started, so the frames covered by the input delay are neutral
+                let neutral: Vec<ControllerInput> = inputs
+                    .iter()
+                    .map(|x| ControllerInput {
+                        plugged_in: x.plugged_in,
+                        ..ControllerInput::empty()
+                    })
+                    .collect();
+                for _ in 1..frame {
+                    self.push_local_inputs(neutral.clone());
+                }
+            }
+
+            // Peers never confirm frame 0
+            if frame > 0 {
+                self.push_local_inputs(inputs);
+            }
+            self.send_unacked_inputs();
+        }
+    }
+
+    /// local_inputs starts from frame 1 and has no gaps, so the next frame is always the length + 1
+    fn push_local_inputs(&mut self, inputs: Vec<ControllerInput>) {
+        if self.unacked_inputs.is_empty() {
+            self.unacked_first_frame = self.local_inputs.len() + 1;
+        }
+        self.local_inputs.push(inputs.clone());
+        self.unacked_inputs.push_back(inputs);
+    }
+
+    /// Send each peer every local input it has not acknowledged, starting from the oldest.
+    /// Because every packet repeats all unacknowledged inputs, a dropped packet is recovered by the next one.
+    fn send_unacked_inputs(&mut self) {
+        let controllers = self.unacked_inputs.front().map_or(0, |x| x.len());
+        let max_input_frames = max_input_frames_per_packet(controllers);
+        let mut fail = false;
+        for (peer, address) in self.peers.iter().enumerate() {
+            let skip = (self.peer_acks[peer] + 1).saturating_sub(self.unacked_first_frame);
+            let input_confirm = InputConfirm {
+                first_frame: self.unacked_first_frame + skip,
+                inputs: self
+                    .unacked_inputs
+                    .iter()
+                    .skip(skip)
+                    .take(max_input_frames)
+                    .cloned()
+                    .collect(),
+                ack: self.confirmed_inputs[peer].len(),
+                frame: self.state_frame,
+            };
+            if self
+                .send_to(address, Message::InputConfirm(input_confirm))
+                .is_err()
+            {
+                fail = true;
+                break;
+            }
+        }
+        if fail {
+            self.disconnect_with_reason("Peer is inaccessible: failed to send controller input");
+        }
+    }
+}
+
+/// Deterministically hashes a game state so that it can be compared with the game state of peers
+pub fn state_hash<T: Serialize>(state: &T) -> u32 {
+    crc32fast::hash(&bincode::serialize(state).unwrap())
+}
+
+/// Returns the number of frames of inputs for the specified number of controllers that fit in a single InputConfirm packet.
+/// Never more than MAX_INPUT_FRAMES_PER_PACKET.
+fn max_input_frames_per_packet(controllers: usize) -> usize {
+    let controller_len = bincode::serialized_size(&ControllerInput::default()).unwrap() as usize;
+    // each frame is a Vec of controllers, prefixed by its length
+    max_frames_per_packet(
+        8 + controllers * controller_len,
+        MAX_INPUT_FRAMES_PER_PACKET,
+    )
+}
+
+/// Returns the number of frames, each serializing to frame_len bytes, that fit in a single InputConfirm or SpectatorInputs packet.
+/// Never more than max_frames.
+fn max_frames_per_packet(frame_len: usize, max_frames: usize) -> usize {
+    let available = codec::MAX_PACKET_LEN - codec::HEADER_LEN - INPUTS_MESSAGE_OVERHEAD;
+    (available / frame_len).min(max_frames)
+}
+
+/// Returns the values rearranged so that the nth value is values[order[n]]
+fn reorder<T: Clone>(values: &[T], order: &[usize]) -> Vec<T> {
+    order.iter().map(|i| values[*i].clone()).collect()
+}
+
+/// Stores a value for each of the most recently stepped frames so that the game can be rolled back to them.
+/// Values older than the capacity of the buffer are discarded.
+#[derive(Clone)]
+pub struct RollbackBuffer<T> {
+    values: VecDeque<T>,
+    first_frame: usize,
+    capacity: usize,
+}
+
+impl<T> RollbackBuffer<T> {
+    pub fn new(capacity: usize) -> RollbackBuffer<T> {
+        RollbackBuffer {
+            values: VecDeque::with_capacity(capacity + 1),
+            first_frame: 0,
+            capacity,
+        }
+    }
+
+    /// Store the value for the specified frame.
+    /// Any values stored for this frame or later frames are discarded.
+    pub fn push(&mut self, frame: usize, value: T) {
+        if frame < self.first_frame || frame > self.first_frame + self.values.len() {
+            self.values.clear();
+            self.first_frame = frame;
+        } else {
+            self.values.truncate(frame - self.first_frame);
+        }
+
+        self.values.push_back(value);
+        if self.values.len() > self.capacity {
+            self.values.pop_front();
+            self.first_frame += 1;
+        }
+    }
+
+    /// Returns the value stored for the specified frame, if it is still in the buffer.
+    pub fn get(&self, frame: usize) -> Option<&T> {
+        frame
+            .checked_sub(self.first_frame)
+            .and_then(|i| self.values.get(i))
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.first_frame = 0;
+    }
+}
+
+impl<T> Default for RollbackBuffer<T> {
+    fn default() -> RollbackBuffer<T> {
+        // Need one extra for the frame before the first mispredicted frame
+        RollbackBuffer::new(MAX_ROLLBACK_FRAMES + 2)
+    }
+}
+
+/// State flow sequence:
+///     Offline -> MatchMaking -> InitConnection -> Ping Test -> Running -> Disconnected -> Offline
+#[derive(Clone)]
+pub enum NetplayState {
+    Offline,
+    Running,
+    InitConnection(InitConnection),
+    MatchMaking {
+        request: MatchMakingRequest,
+        /// The address of the matchmaking server
+        host: String,
+    },
+    Disconnected {
+        reason: String,
+    },
+    PingTest {
+        local_init: InitConnection,
+        /// structure: peers Vec<ping id [Ping]>
+        pings: Vec<[Ping; 255]>,
+    },
+    /// Waiting for the host to send the inputs of the session being spectated
+    SpectatorWait,
+}
+
+impl NetplayState {
+    pub fn to_string(&self) -> String {
+        match self {
+            NetplayState::Offline => String::from("Offline"),
+            NetplayState::Running => String::from("Running"),
+            NetplayState::InitConnection(_) => String::from("InitConnection"),
+            NetplayState::MatchMaking { .. } => String::from("MatchMaking"),
+            NetplayState::Disconnected { .. } => String::from("Disconnected"),
+            NetplayState::PingTest { .. } => String::from("PingTest"),
+            NetplayState::SpectatorWait => String::from("SpectatorWait"),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchMakingRequest {
+    pub region: String,
+    pub build_version: String,
+    /// The total number of players including the requester
+    pub num_players: u8,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MatchMakingResponse {
+    /// The addresses of every other player in the match
+    pub addresses: Vec<SocketAddr>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InitConnection {
+    random: u64,
+}
+
+#[derive(Clone, Default, Copy)]
+pub struct Ping {
+    time_sent: Option<Instant>,
+    time_received: Option<Instant>,
+}
+
+/// Contains every local input the receiver has not acknowledged, so packet loss only delays inputs until the next packet arrives.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputConfirm {
+    /// The frame of the first element in inputs
+    first_frame: usize,
+    /// structure: frames Vec<controllers Vec<ControllerInput>>
+    inputs: Vec<Vec<ControllerInput>>,
+    /// The highest frame for which the sender has received every input from the receiver
+    ack: usize,
+    /// The frame the sender was on, used to estimate which machine is running ahead
+    frame: usize,
+}
+
+/// Hashes of the senders game state for consecutive confirmed frames
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StateHashes {
+    first_frame: usize,
+    hashes: Vec<u32>,
+}
+
+struct Spectator {
+    address: SocketAddr,
+    /// The number of frames of inputs the spectator has received
+    ack: usize,
+}
+
+/// Sent by a spectator every frame to request the next inputs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpectatorRequest {
+    /// The number of frames of inputs the spectator has received
+    ack: usize,
+}
+
+/// The inputs of every player for consecutive confirmed frames
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpectatorInputs {
+    seed: u64,
+    num_players: usize,
+    /// The frame of the first element in inputs
+    first_frame: usize,
+    /// structure: frames Vec<players Vec<controllers Vec<ControllerInput>>>
+    inputs: Vec<Vec<Vec<ControllerInput>>>,
+}
+
+#[test]
+fn rollback_buffer_discards_frames_beyond_capacity() {
+    let mut buffer = RollbackBuffer::new(3);
+    for frame in 0..5 {
+        buffer.push(frame, frame * 10);
+    }
+    assert_eq!(buffer.get(0), None);
+    assert_eq!(buffer.get(1), None);
+    assert_eq!(buffer.get(2), Some(&20));
+    assert_eq!(buffer.get(3), Some(&30));
+    assert_eq!(buffer.get(4), Some(&40));
+    assert_eq!(buffer.get(5), None);
+}
+
+#[test]
+fn rollback_buffer_restores_oldest_frame() {
+    let mut buffer = RollbackBuffer::new(3);
+    for frame in 0..5 {
+        buffer.push(frame, frame * 10);
+    }
+
+    // rolling back to the oldest frame still kept replaces it and discards every later frame
+    assert_eq!(buffer.get(2), Some(&20));
+    buffer.push(2, 21);
+    assert_eq!(buffer.get(2), Some(&21));
+    assert_eq!(buffer.get(3), None);
+    assert_eq!(buffer.get(4), None);
+
+    buffer.push(3, 31);
+    buffer.push(4, 41);
+    buffer.push(5, 51);
+    assert_eq!(buffer.get(2), None);
+    assert_eq!(buffer.get(3), Some(&31));
+    assert_eq!(buffer.get(5), Some(&51));
+}
+
+#[test]
+fn input_confirm_fits_in_packet_with_many_controllers() {
+    for controllers in [1, 4, 16, 64] {
+        let frames = max_input_frames_per_packet(controllers);
+        assert!(frames > 0);
+        let input_confirm = InputConfirm {
+            first_frame: 1,
+            inputs: vec![vec![ControllerInput::default(); controllers]; frames],
+            ack: 0,
+            frame: 0,
+        };
+        let packet = Packet {
+            build_id: codec::build_id("test"),
+            session_id: 0,
+            message: Message::InputConfirm(input_confirm),
+        };
+        let data = codec::encode(&packet).unwrap();
+        assert!(data.len() <= codec::MAX_PACKET_LEN);
+        assert!(codec::decode(&data).is_ok());
+    }
+    assert_eq!(max_input_frames_per_packet(4), MAX_INPUT_FRAMES_PER_PACKET);
+}
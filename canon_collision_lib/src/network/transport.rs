@@ -0,0 +1,129 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+
+/// The port netplay binds to and connects to when none is specified
+pub const DEFAULT_PORT: u16 = 8413;
+
+/// Sends and receives the packets of a Netplay session.
+/// Packets may be dropped, duplicated or arrive out of order.
+pub trait Transport: Send {
+    /// Returns the length of the received packet and its sender, or None if no packet is waiting.
+    fn recv_from(&self, buf: &mut [u8]) -> Option<(usize, SocketAddr)>;
+
+    fn send_to(&self, data: &[u8], address: SocketAddr) -> io::Result<()>;
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn new(port: u16) -> UdpTransport {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).unwrap();
+        socket.set_nonblocking(true).unwrap();
+        UdpTransport { socket }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn recv_from(&self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
+        // returns Err if there is no packet waiting
+        self.socket.recv_from(buf).ok()
+    }
+
+    fn send_to(&self, data: &[u8], address: SocketAddr) -> io::Result<()> {
+        self.socket.send_to(data, address).map(|_| ())
+    }
+}
+
+/// Simulates a network connecting any number of ChannelTransport within the same process.
+/// Time only passes when step is called, so a session run over it is deterministic.
+pub struct ChannelNetwork {
+    /// The number of steps it takes for a packet to arrive
+    latency: usize,
+    /// The maximum number of extra steps randomly added to the latency of each packet, causing packets to arrive out of order
+    jitter: usize,
+    /// The chance of a packet being dropped, between 0.0 and 1.0
+    loss: f64,
+    rng: StdRng,
+    step: usize,
+    in_flight: Vec<InFlightPacket>,
+}
+
+struct InFlightPacket {
+    arrival_step: usize,
+    from: SocketAddr,
+    to: SocketAddr,
+    data: Vec<u8>,
+}
+
+impl ChannelNetwork {
+    pub fn new(latency: usize, jitter: usize, loss: f64, seed: u64) -> Arc<Mutex<ChannelNetwork>> {
+        Arc::new(Mutex::new(ChannelNetwork {
+            latency,
+            jitter,
+            loss,
+            rng: StdRng::seed_from_u64(seed),
+            step: 0,
+            in_flight: vec![],
+        }))
+    }
+
+    /// Create a transport that sends and receives packets on the network with the specified address
+    pub fn transport(
+        network: &Arc<Mutex<ChannelNetwork>>,
+        address: SocketAddr,
+    ) -> ChannelTransport {
+        ChannelTransport {
+            network: network.clone(),
+            address,
+        }
+    }
+
+    /// Call this once every frame
+    pub fn step(&mut self) {
+        self.step += 1;
+    }
+}
+
+pub struct ChannelTransport {
+    network: Arc<Mutex<ChannelNetwork>>,
+    address: SocketAddr,
+}
+
+impl Transport for ChannelTransport {
+    fn recv_from(&self, buf: &mut [u8]) -> Option<(usize, SocketAddr)> {
+        let mut network = self.network.lock().unwrap();
+        let step = network.step;
+        let index = network
+            .in_flight
+            .iter()
+            .position(|x| x.to == self.address && x.arrival_step <= step)?;
+        let packet = network.in_flight.remove(index);
+
+        // behave like UdpSocket and truncate packets that dont fit in the buffer
+        let len = packet.data.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet.data[..len]);
+        Some((len, packet.from))
+    }
+
+    fn send_to(&self, data: &[u8], address: SocketAddr) -> io::Result<()> {
+        let mut network = self.network.lock().unwrap();
+        let loss = network.loss;
+        if !network.rng.gen_bool(loss) {
+            let jitter = network.jitter;
+            let arrival_step = network.step + network.latency + network.rng.gen_range(0..=jitter);
+            network.in_flight.push(InFlightPacket {
+                arrival_step,
+                from: self.address,
+                to: address,
+                data: data.to_vec(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -30,6 +30,20 @@ impl Package {
         !self.package_updates.is_empty()
     }
 
+    /// Identifies the gameplay data of the package, a replay can only be resimulated by a package with the same hash.
+    pub fn compute_hash(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        for (key, entity) in self.entities.key_value_iter() {
+            hasher.update(key.as_bytes());
+            hasher.update(&bincode::serialize(entity).unwrap());
+        }
+        for (key, stage) in self.stages.key_value_iter() {
+            hasher.update(key.as_bytes());
+            hasher.update(&bincode::serialize(stage).unwrap());
+        }
+        hasher.finalize()
+    }
+
     /// Loads and returns the package with the specified name.
     /// Returns None if the package doesnt exist or is broken.
     pub fn open(path: PathBuf) -> Option<Package> {
@@ -1,11 +1,299 @@
 use std::cmp::Ordering;
 use std::fs;
-use std::path::PathBuf;
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 
-use chrono::DateTime;
+use chrono::{DateTime, Local};
 
 use crate::files;
 
+/*  Replay File Format:
+    Header:
+        8 bytes - magic: "CCREPLAY"
+        2 bytes - format version
+        4 bytes - metadata length
+
+    Metadata:
+        n bytes - bincode serialized ReplayMetadata
+
+    Body:
+        n bytes - deflate compressed, bincode serialized ReplayBody (defined in canon_collision)
+
+    All integers are little endian.
+    The metadata can be read without reading the body, so replays can be listed and inspected cheaply by any tool.
+    The input history is run length encoded before compression, as inputs are usually unchanged for many frames.
+*/
+
+pub const REPLAY_EXTENSION: &str = "replay";
+
+const MAGIC: &[u8; 8] = b"CCREPLAY";
+
+/// Increment whenever ReplayMetadata or ReplayBody changes
+pub const FORMAT_VERSION: u16 = 5;
+
+const HEADER_LEN: usize = 14;
+
+/// Metadata is only a few hundred bytes, a longer length in the header means the file is corrupted.
+/// Checked before allocating so that a corrupted length cannot exhaust memory.
+const MAX_METADATA_LEN: usize = 64 * 1024;
+
+/// The fighter and team of a player when the game started
+#[derive(Clone, Default, Serialize, Deserialize, Node)]
+pub struct PlayerSetup {
+    pub fighter: String,
+    pub team: usize,
+}
+
+/// Describes a replay without needing to read its inputs
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ReplayMetadata {
+    pub timestamp: DateTime<Local>,
+    /// The game can only be resimulated by the build it was recorded with
+    pub build_version: String,
+    /// The game can only be resimulated by a package with the same gameplay data it was recorded with
+    pub package_hash: u32,
+    pub stage: String,
+    pub players: Vec<PlayerSetup>,
+    /// The number of frames in the replay
+    pub duration: usize,
+    /// The index of the winning player, None if the replay was saved before the game ended
+    pub winner: Option<usize>,
+    /// Favourite replays are never pruned
+    pub favourite: bool,
+}
+
+impl ReplayMetadata {
+    /// A single line summary to display in replay listings
+    pub fn description(&self) -> String {
+        let fighters: Vec<&str> = self.players.iter().map(|x| x.fighter.as_ref()).collect();
+        let seconds = self.duration / 60;
+        let winner = match self.winner.and_then(|x| self.players.get(x)) {
+            Some(winner) => format!("{} won", winner.fighter),
+            None => String::from("Unfinished"),
+        };
+        format!(
+            "{}{} - {} - {} - {}:{:02} - {}",
+            if self.favourite { "* " } else { "" },
+            self.timestamp.format("%Y-%m-%d %H:%M"),
+            self.stage,
+            fighters.join(" vs "),
+            seconds / 60,
+            seconds % 60,
+            winner
+        )
+    }
+
+    /// Fills in a replay name template.
+    /// `{timestamp}`, `{stage}` and `{players}` are replaced with the details of the replay.
+    pub fn name_from_template(&self, template: &str) -> String {
+        let fighters: Vec<&str> = self.players.iter().map(|x| x.fighter.as_ref()).collect();
+        let name = template
+            .replace(
+                "{timestamp}",
+                &self.timestamp.format("%Y-%m-%d %H-%M-%S").to_string(),
+            )
+            .replace("{stage}", &self.stage)
+            .replace("{players}", &fighters.join(" vs "));
+
+        // Remove characters that are not allowed in file names on some platforms
+        let name: String = name
+            .chars()
+            .map(|c| match c {
+                '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        let name = name.trim();
+        if name.is_empty() {
+            String::from("replay")
+        } else {
+            name.to_string()
+        }
+    }
+}
+
+/// Returns the header and metadata of a replay file, the body is appended to this.
+pub fn encode_header(metadata: &ReplayMetadata) -> Vec<u8> {
+    let metadata = bincode::serialize(metadata).unwrap();
+    let mut data = Vec::with_capacity(HEADER_LEN + metadata.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    data.extend_from_slice(&metadata);
+    data
+}
+
+/// Reads the header and metadata of a replay file, leaving the reader at the start of the body.
+pub fn read_metadata<R: Read>(reader: &mut R) -> Result<ReplayMetadata, String> {
+    let mut header = [0; HEADER_LEN];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| String::from("The file is too short to be a replay"))?;
+
+    if &header[0..8] != MAGIC {
+        return Err(String::from("The file is not a replay"));
+    }
+
+    let version = u16::from_le_bytes([header[8], header[9]]);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "The replay uses format version {} but only format version {} is supported",
+            version, FORMAT_VERSION
+        ));
+    }
+
+    let metadata_len = u32::from_le_bytes(header[10..14].try_into().unwrap()) as usize;
+    if metadata_len > MAX_METADATA_LEN {
+        return Err(format!(
+            "The replay metadata is corrupted: its length of {} bytes is longer than the maximum of {} bytes",
+            metadata_len, MAX_METADATA_LEN
+        ));
+    }
+    let mut metadata = vec![0; metadata_len];
+    reader
+        .read_exact(&mut metadata)
+        .map_err(|_| String::from("The replay metadata is truncated"))?;
+    bincode::deserialize(&metadata).map_err(|x| format!("The replay metadata is corrupted: {}", x))
+}
+
+pub fn load_replay_metadata(name: &str) -> Result<ReplayMetadata, String> {
+    let file = File::open(get_replay_path(name)?).map_err(|x| x.to_string())?;
+    read_metadata(&mut BufReader::new(file))
+}
+
+/// A replay in the replays folder, described only by its metadata
+#[derive(Clone)]
+pub struct ReplayListing {
+    pub name: String,
+    pub description: String,
+    pub favourite: bool,
+}
+
+/// Returns every replay in the replays folder, most recent first.
+/// Replays that cannot be read come last.
+pub fn list_replays() -> Vec<ReplayListing> {
+    let mut replays: Vec<(String, Result<ReplayMetadata, String>)> = get_replay_names()
+        .into_iter()
+        .map(|name| {
+            let metadata = load_replay_metadata(&name);
+            (name, metadata)
+        })
+        .collect();
+    replays.sort_by(|(_, a), (_, b)| match (a, b) {
+        (Ok(a), Ok(b)) => b.timestamp.cmp(&a.timestamp),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => Ordering::Equal,
+    });
+
+    replays
+        .into_iter()
+        .map(|(name, metadata)| match metadata {
+            Ok(metadata) => ReplayListing {
+                description: metadata.description(),
+                favourite: metadata.favourite,
+                name,
+            },
+            Err(err) => ReplayListing {
+                description: format!("{} - {}", name, err),
+                favourite: false,
+                name,
+            },
+        })
+        .collect()
+}
+
+/// Creates a new replay file with a name that is not used by any replay in the replays folder, by appending a number to the name if needed.
+/// The file is created atomically, so a replay saved at the same time by another process is never overwritten.
+/// Returns the name used and the created file.
+pub fn create_unique_replay(name: &str) -> Result<(String, File), String> {
+    DirBuilder::new()
+        .recursive(true)
+        .create(get_replays_dir_path())
+        .map_err(|x| format!("Failed to create the replays folder: {}", x))?;
+
+    let mut unique_name = name.to_string();
+    let mut i = 2;
+    loop {
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(get_replay_path(&unique_name)?)
+        {
+            Ok(file) => return Ok((unique_name, file)),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                unique_name = format!("{} ({})", name, i);
+                i += 1;
+            }
+            Err(err) => {
+                return Err(format!(
+                    "Failed to create replay '{}': {}",
+                    unique_name, err
+                ))
+            }
+        }
+    }
+}
+
+/// Favourite or unfavourite a replay, only the metadata of the file is rewritten
+pub fn set_replay_favourite(name: &str, favourite: bool) -> Result<(), String> {
+    let path = get_replay_path(name)?;
+    let data = fs::read(&path).map_err(|x| format!("Failed to open replay '{}': {}", name, x))?;
+    let mut reader = data.as_slice();
+    let mut metadata = read_metadata(&mut reader)?;
+    metadata.favourite = favourite;
+
+    let mut new_data = encode_header(&metadata);
+    new_data.extend_from_slice(reader);
+
+    // Written to a temporary file first, so the replay is not corrupted if writing fails part way through
+    let temp_path = path.with_extension(format!("{}.tmp", REPLAY_EXTENSION));
+    let result = fs::write(&temp_path, new_data).and_then(|_| fs::rename(&temp_path, &path));
+    if let Err(err) = result {
+        fs::remove_file(&temp_path).ok();
+        return Err(format!("Failed to write replay '{}': {}", name, err));
+    }
+    Ok(())
+}
+
+/// Deletes the oldest replays that are not favourites, until there are at most max_count of them using at most max_bytes.
+/// The replay named keep is never deleted and is counted as the most recent replay, so a newly saved replay always survives.
+/// Returns the names of the deleted replays.
+pub fn prune_replays(max_count: Option<usize>, max_bytes: Option<u64>, keep: &str) -> Vec<String> {
+    if max_count.is_none() && max_bytes.is_none() {
+        return vec![];
+    }
+
+    // most recent first
+    let mut replays: Vec<(String, DateTime<Local>, u64)> = get_replay_names()
+        .into_iter()
+        .filter_map(|name| {
+            let metadata = load_replay_metadata(&name).ok()?;
+            if metadata.favourite {
+                return None;
+            }
+            let size = fs::metadata(get_replay_path(&name).ok()?).ok()?.len();
+            Some((name, metadata.timestamp, size))
+        })
+        .collect();
+    replays.sort_by(|a, b| (b.0 == keep).cmp(&(a.0 == keep)).then(b.1.cmp(&a.1)));
+
+    let mut total_bytes = 0;
+    let mut deleted = vec![];
+    for (i, (name, _, size)) in replays.into_iter().enumerate() {
+        total_bytes += size;
+        let over_count = max_count.map_or(false, |max| i >= max);
+        let over_bytes = max_bytes.map_or(false, |max| total_bytes > max);
+        if (over_count || over_bytes) && name != keep && delete_replay(&name).is_ok() {
+            deleted.push(name);
+        }
+    }
+    deleted
+}
+
+/// Returns the names of every replay in the replays folder, excluding the extension, most recent first
 pub fn get_replay_names() -> Vec<String> {
     let mut result: Vec<String> = vec![];
 
@@ -15,7 +303,7 @@ pub fn get_replay_names() -> Vec<String> {
                 let file_name = file.file_name().into_string().unwrap();
                 if let Some(split_point) = file_name.rfind('.') {
                     let (name, ext) = file_name.split_at(split_point);
-                    if ext.to_lowercase() == ".zip" {
+                    if ext[1..].to_lowercase() == REPLAY_EXTENSION {
                         result.push(name.to_string());
                     }
                 }
@@ -23,25 +311,32 @@ pub fn get_replay_names() -> Vec<String> {
         }
     }
 
-    // Most recent dates come first
-    // Dates come before non-dates
-    // Non-dates are sorted alphabetically
-    result.sort_by(|a, b| {
-        let a_dt = DateTime::parse_from_rfc2822(a);
-        let b_dt = DateTime::parse_from_rfc2822(b);
-        if a_dt.is_err() && b_dt.is_err() {
-            a.cmp(b)
-        } else if let Ok(a_dt) = a_dt {
-            if let Ok(b_dt) = b_dt {
-                a_dt.cmp(&b_dt).reverse()
-            } else {
-                Ordering::Less
-            }
-        } else {
-            Ordering::Greater
-        }
+    // Most recent first, replays without a readable timestamp come last and are sorted alphabetically
+    let mut replays: Vec<(String, Option<DateTime<Local>>)> = result
+        .into_iter()
+        .map(|name| {
+            let timestamp = replay_timestamp(&name);
+            (name, timestamp)
+        })
+        .collect();
+    replays.sort_by(|(a, a_time), (b, b_time)| match (a_time, b_time) {
+        (Some(a_time), Some(b_time)) => b_time.cmp(a_time).then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
     });
-    result
+    replays.into_iter().map(|(name, _)| name).collect()
+}
+
+/// The time the replay was recorded, falls back to the modification time of the file if the metadata cannot be read
+fn replay_timestamp(name: &str) -> Option<DateTime<Local>> {
+    match load_replay_metadata(name) {
+        Ok(metadata) => Some(metadata.timestamp),
+        Err(_) => fs::metadata(get_replay_path(name).ok()?)
+            .and_then(|x| x.modified())
+            .ok()
+            .map(DateTime::from),
+    }
 }
 
 fn get_replays_dir_path() -> PathBuf {
@@ -50,12 +345,80 @@ fn get_replays_dir_path() -> PathBuf {
     replays_path
 }
 
-pub fn get_replay_path(name: &str) -> PathBuf {
+/// Returns the path of the replay with the specified name, excluding the extension.
+/// Fails if the name could refer to a file outside of the replays folder.
+pub fn get_replay_path(name: &str) -> Result<PathBuf, String> {
+    check_replay_name(name)?;
     let mut replay_path = get_replays_dir_path();
-    replay_path.push(name);
-    replay_path
+    replay_path.push(format!("{}.{}", name, REPLAY_EXTENSION));
+    Ok(replay_path)
+}
+
+/// Replay names come from users, so make sure they cant refer to a file outside of the replays folder
+fn check_replay_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.contains("..") {
+        Err(format!(
+            "'{}' is not a valid replay name, it must not be empty or contain '/', '\\' or '..'",
+            name
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub fn delete_replay(name: &str) -> Result<(), String> {
+    fs::remove_file(get_replay_path(name)?)
+        .map_err(|x| format!("Failed to delete replay '{}': {}", name, x))
+}
+
+/// Renames the replay, failing instead of overwriting a replay that already has the new name
+pub fn rename_replay(name: &str, new_name: &str) -> Result<(), String> {
+    let path = get_replay_path(name)?;
+    let new_path = get_replay_path(new_name)?;
+    if !path.exists() {
+        return Err(format!("There is no replay named '{}'", name));
+    }
+    if new_path.exists() {
+        return Err(format!("A replay named '{}' already exists", new_name));
+    }
+    fs::rename(path, new_path).map_err(|x| format!("Failed to rename replay '{}': {}", name, x))
+}
+
+/// Copies the replay out of the replays folder so it can be shared
+pub fn export_replay(name: &str, destination: &Path) -> Result<(), String> {
+    let path = get_replay_path(name)?;
+    // check it is actually a replay before copying
+    read_metadata(&mut BufReader::new(
+        File::open(&path).map_err(|x| format!("Failed to open replay '{}': {}", name, x))?,
+    ))?;
+    let destination = if destination.is_dir() {
+        destination.join(format!("{}.{}", name, REPLAY_EXTENSION))
+    } else {
+        destination.to_path_buf()
+    };
+    fs::copy(&path, &destination)
+        .map(|_| ())
+        .map_err(|x| format!("Failed to export replay '{}': {}", name, x))
+}
+
+#[test]
+fn read_metadata_rejects_oversized_length() {
+    let mut data = vec![];
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    data.extend_from_slice(&u32::MAX.to_le_bytes());
+    let err = read_metadata(&mut data.as_slice()).err().unwrap();
+    assert!(err.starts_with("The replay metadata is corrupted"));
 }
 
-pub fn delete_replay(name: &str) {
-    fs::remove_file(get_replay_path(&format!("{}.zip", name))).ok();
+#[test]
+fn replay_names_cannot_leave_replays_folder() {
+    assert!(check_replay_name("2026-10-17 Toriel vs Toriel").is_ok());
+    assert!(check_replay_name("").is_err());
+    assert!(check_replay_name("../config").is_err());
+    assert!(check_replay_name("..").is_err());
+    assert!(check_replay_name("subfolder/replay").is_err());
+    assert!(check_replay_name("subfolder\\replay").is_err());
+    assert!(rename_replay("replay", "../../outside").is_err());
+    assert!(get_replay_path("../config").is_err());
 }
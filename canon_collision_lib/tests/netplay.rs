@@ -0,0 +1,145 @@
+use canon_collision_lib::config::Config;
+use canon_collision_lib::input::state::ControllerInput;
+use canon_collision_lib::input::Input;
+use canon_collision_lib::network::{ChannelNetwork, Netplay, NetplayState};
+
+use std::net::SocketAddr;
+
+/// The inputs sent on the specified frame, these never match the neutral inputs filling the input delay
+fn inputs_for_frame(frame: usize) -> Vec<ControllerInput> {
+    vec![ControllerInput {
+        plugged_in: true,
+        stick_x: (frame + 1) as f32 / 10000.0,
+        ..Default::default()
+    }]
+}
+
+/// Connects two peers over a simulated network and steps them the same way the game loop does.
+/// When game_start_frame is set, each peer starts a game once it reaches that session frame.
+/// Returns each peers Netplay after the specified number of frames.
+fn run_session(
+    latency: usize,
+    jitter: usize,
+    loss: f64,
+    frames: usize,
+    input_delay: Option<usize>,
+    game_start_frame: Option<usize>,
+) -> Vec<Netplay> {
+    let network = ChannelNetwork::new(latency, jitter, loss, 0);
+    let config = Config {
+        netplay_input_delay: input_delay,
+        ..Config::default()
+    };
+    let addresses: Vec<SocketAddr> = vec![
+        "10.0.0.1:8413".parse().unwrap(),
+        "10.0.0.2:8413".parse().unwrap(),
+    ];
+    let mut peers: Vec<Netplay> = addresses
+        .iter()
+        .map(|address| Netplay::new(Box::new(ChannelNetwork::transport(&network, *address))))
+        .collect();
+    for peer in peers.iter_mut() {
+        peer.set_config(&config);
+    }
+    peers[0].direct_connect(&addresses[1..2]);
+    peers[1].direct_connect(&addresses[0..1]);
+
+    for _ in 0..frames {
+        network.lock().unwrap().step();
+        for peer in peers.iter_mut() {
+            peer.step();
+            match peer.state() {
+                NetplayState::Running => {
+                    if !peer.skip_frame() {
+                        let frame = peer.frame();
+                        peer.send_controller_inputs(inputs_for_frame(frame));
+                    }
+                    if Some(peer.frame()) == game_start_frame {
+                        peer.reset_frame_origin();
+                    }
+                }
+                NetplayState::Disconnected { reason } => panic!("Peer disconnected: {}", reason),
+                _ => {}
+            }
+        }
+    }
+    peers
+}
+
+fn assert_inputs_confirmed(peers: &[Netplay], min_frames: usize, input_delay: usize) {
+    assert_ne!(peers[0].local_index(), peers[1].local_index());
+    assert_eq!(peers[0].get_seed(), peers[1].get_seed());
+
+    for peer in peers {
+        let confirmed = &peer.confirmed_inputs[0];
+        assert!(
+            confirmed.len() >= min_frames,
+            "only {} frames were confirmed",
+            confirmed.len()
+        );
+        // Confirmed inputs start from frame 1, inputs sent on frame n are confirmed for frame n + input_delay.
+        // The frames before the first sent input are neutral.
+        for (i, inputs) in confirmed.iter().enumerate() {
+            let frame = i + 1;
+            let expected = if frame < input_delay {
+                vec![ControllerInput {
+                    plugged_in: true,
+                    ..ControllerInput::empty()
+                }]
+            } else {
+                inputs_for_frame(frame - input_delay)
+            };
+            assert!(inputs == &expected, "frame {} mismatched", frame);
+        }
+    }
+}
+
+#[test]
+fn netplay_session_over_perfect_network() {
+    let peers = run_session(0, 0, 0.0, 1000, Some(0), None);
+    assert_inputs_confirmed(&peers, 500, 0);
+}
+
+#[test]
+fn netplay_session_with_latency_and_loss() {
+    let peers = run_session(4, 3, 0.1, 1500, Some(0), None);
+    assert_inputs_confirmed(&peers, 500, 0);
+}
+
+#[test]
+fn netplay_session_with_input_delay() {
+    let peers = run_session(4, 0, 0.0, 1000, Some(3), None);
+    for peer in &peers {
+        assert_eq!(peer.input_delay(), 3);
+    }
+    assert_inputs_confirmed(&peers, 500, 3);
+
+    // The first inputs are sent on frame 0, so they first appear on frame 3 which is confirmed_inputs[2]
+    for peer in &peers {
+        let confirmed = &peer.confirmed_inputs[0];
+        assert!(confirmed[1] != inputs_for_frame(0));
+        assert!(confirmed[2] == inputs_for_frame(0));
+    }
+}
+
+#[test]
+fn netplay_game_started_after_menu_frames() {
+    let game_start_frame = 200;
+    let peers = run_session(0, 0, 0.0, 1000, Some(0), Some(game_start_frame));
+    let input = Input::headless();
+    for peer in &peers {
+        assert_eq!(peer.frame_origin(), game_start_frame);
+        let confirmed_game_frames = peer.confirmed_frames().saturating_sub(game_start_frame);
+        assert!(confirmed_game_frames >= 500);
+
+        // Frame n of the game uses the inputs sent on session frame game_start_frame + n, not the inputs from the menu.
+        // This applies to the local player as well as the remote player.
+        for frame in 1..confirmed_game_frames {
+            let expected = inputs_for_frame(game_start_frame + frame)[0].stick_x;
+            let players = input.players_no_log(frame, peer);
+            for player in players {
+                assert_eq!(player.stick_x.value, expected, "frame {} mismatched", frame);
+            }
+        }
+    }
+}
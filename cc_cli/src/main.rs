@@ -1,7 +1,10 @@
+use canon_collision_lib::replays_files;
+
 use std::env;
 use std::io::Read;
 use std::io::Write;
 use std::net::TcpStream;
+use std::path::Path;
 
 fn main() {
     std::process::exit(main_main());
@@ -11,6 +14,18 @@ fn main_main() -> i32 {
     let mut args = env::args();
     args.next();
     let out_vec: Vec<String> = args.collect();
+
+    // Replays are managed offline, everything else is a command for the running game
+    if out_vec.first().map(|x| x.as_ref()) == Some("replay") {
+        return match replay_command(&out_vec[1..]) {
+            Ok(()) => 0,
+            Err(e) => {
+                println!("{}", e);
+                1
+            }
+        };
+    }
+
     let out: String = format!("C{}", out_vec.join(" "));
 
     match TcpStream::connect("127.0.0.1:1613") {
@@ -29,3 +44,90 @@ fn main_main() -> i32 {
         }
     }
 }
+
+const REPLAY_USAGE: &str = "Usage:
+    cc_cli replay list
+    cc_cli replay inspect NAME
+    cc_cli replay rename NAME NEW_NAME
+    cc_cli replay delete NAME
+    cc_cli replay export NAME PATH
+    cc_cli replay favourite NAME
+    cc_cli replay unfavourite NAME";
+
+fn replay_command(args: &[String]) -> Result<(), String> {
+    let args: Vec<&str> = args.iter().map(|x| x.as_ref()).collect();
+    match args.as_slice() {
+        ["list"] => {
+            let replays = replays_files::list_replays();
+            if replays.is_empty() {
+                println!("There are no replays");
+            }
+            for replay in replays {
+                println!("{}\n    {}", replay.name, replay.description);
+            }
+            Ok(())
+        }
+        ["inspect", name] => {
+            let metadata = replays_files::load_replay_metadata(name)?;
+            let seconds = metadata.duration / 60;
+            println!("Name:         {}", name);
+            println!(
+                "Recorded:     {}",
+                metadata.timestamp.format("%Y-%m-%d %H:%M:%S")
+            );
+            println!("Build:        {}", metadata.build_version);
+            println!("Package hash: {:08x}", metadata.package_hash);
+            println!("Stage:        {}", metadata.stage);
+            println!(
+                "Duration:     {}:{:02} ({} frames)",
+                seconds / 60,
+                seconds % 60,
+                metadata.duration
+            );
+            println!("Favourite:    {}", metadata.favourite);
+            println!("Players:");
+            for (i, player) in metadata.players.iter().enumerate() {
+                println!("    {}. {} (team {})", i + 1, player.fighter, player.team);
+            }
+            match metadata
+                .winner
+                .and_then(|x| metadata.players.get(x).map(|y| (x, y)))
+            {
+                Some((i, winner)) => {
+                    println!("Result:       Player {} ({}) won", i + 1, winner.fighter)
+                }
+                None => println!("Result:       Unfinished"),
+            }
+            Ok(())
+        }
+        ["rename", name, new_name] => {
+            replays_files::rename_replay(name, new_name)?;
+            println!("Renamed replay '{}' to '{}'", name, new_name);
+            Ok(())
+        }
+        ["delete", name] => {
+            replays_files::delete_replay(name)?;
+            println!("Deleted replay '{}'", name);
+            Ok(())
+        }
+        ["export", name, path] => {
+            replays_files::export_replay(name, Path::new(path))?;
+            println!("Exported replay '{}' to {}", name, path);
+            Ok(())
+        }
+        ["favourite", name] => {
+            replays_files::set_replay_favourite(name, true)?;
+            println!(
+                "Replay '{}' is now a favourite and will not be pruned",
+                name
+            );
+            Ok(())
+        }
+        ["unfavourite", name] => {
+            replays_files::set_replay_favourite(name, false)?;
+            println!("Replay '{}' is no longer a favourite", name);
+            Ok(())
+        }
+        _ => Err(String::from(REPLAY_USAGE)),
+    }
+}
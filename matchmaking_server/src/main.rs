@@ -0,0 +1,239 @@
+use canon_collision_lib::network::codec::{self, Message, Packet};
+use canon_collision_lib::network::{MatchMakingRequest, MatchMakingResponse, DEFAULT_PORT};
+
+use std::collections::HashMap;
+use std::env;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Clients resend their request every 10 seconds, so a request that has not been resent within this time was cancelled.
+const REQUEST_EXPIRY: Duration = Duration::from_secs(30);
+
+/// How long a match is resent to its players, in case the original response was dropped.
+const MATCH_EXPIRY: Duration = Duration::from_secs(20);
+
+fn main() {
+    let port = match env::args().nth(1).map(|x| x.parse()) {
+        Some(Ok(port)) => port,
+        Some(Err(_)) => {
+            println!("Usage: cc_matchmaking_server [PORT]");
+            return;
+        }
+        None => DEFAULT_PORT,
+    };
+
+    let socket = UdpSocket::bind(("0.0.0.0", port)).unwrap();
+    // wake up regularly to expire requests even when no packets are arriving
+    socket
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    println!("Listening for matchmaking requests on port {}", port);
+
+    let mut server = MatchMakingServer::new();
+    let mut buf = [0; codec::MAX_PACKET_LEN];
+    loop {
+        if let Ok((len, address)) = socket.recv_from(&mut buf) {
+            match codec::decode(&buf[..len]) {
+                Ok(Packet {
+                    message: Message::MatchMakingRequest(request),
+                    ..
+                }) => {
+                    // Clients only accept packets from their own build
+                    let build_id = codec::build_id(&request.build_version);
+                    for (address, response) in server.request(address, request, Instant::now()) {
+                        let packet = Packet {
+                            build_id,
+                            session_id: 0,
+                            message: Message::MatchMakingResponse(response),
+                        };
+                        match codec::encode(&packet) {
+                            Ok(data) => {
+                                if let Err(err) = socket.send_to(&data, address) {
+                                    println!("Failed to send match to {}: {}", address, err);
+                                }
+                            }
+                            Err(err) => println!("Failed to encode match for {}: {}", address, err),
+                        }
+                    }
+                }
+                Ok(_) => println!("Ignored non matchmaking packet from {}", address),
+                Err(err) => println!("Discarded packet from {}: {}", address, err),
+            }
+        }
+        server.expire(Instant::now());
+    }
+}
+
+/// Players can only be matched with players requesting the same bucket
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Bucket {
+    region: String,
+    build_version: String,
+    num_players: u8,
+}
+
+struct WaitingPlayer {
+    address: SocketAddr,
+    last_request: Instant,
+}
+
+struct Match {
+    /// structure: Vec<player address>
+    players: Vec<SocketAddr>,
+    created: Instant,
+}
+
+struct MatchMakingServer {
+    /// Players are matched in the order they first requested a match
+    waiting: HashMap<Bucket, Vec<WaitingPlayer>>,
+    /// structure: player address HashMap<Match>
+    matched: HashMap<SocketAddr, Match>,
+}
+
+impl MatchMakingServer {
+    fn new() -> MatchMakingServer {
+        MatchMakingServer {
+            waiting: HashMap::new(),
+            matched: HashMap::new(),
+        }
+    }
+
+    /// Returns the responses to send when the request completes a match
+    fn request(
+        &mut self,
+        address: SocketAddr,
+        request: MatchMakingRequest,
+        now: Instant,
+    ) -> Vec<(SocketAddr, MatchMakingResponse)> {
+        if let Some(found) = self.matched.get(&address) {
+            // The player is still requesting so the previous response may have been dropped
+            return vec![(address, response(address, &found.players))];
+        }
+
+        if request.num_players < 2 {
+            return vec![];
+        }
+        let num_players = request.num_players as usize;
+        let bucket = Bucket {
+            region: request.region,
+            build_version: request.build_version,
+            num_players: request.num_players,
+        };
+
+        // A player can only wait for one match at a time
+        for (other_bucket, waiting) in self.waiting.iter_mut() {
+            if other_bucket != &bucket {
+                waiting.retain(|x| x.address != address);
+            }
+        }
+
+        let waiting = self.waiting.entry(bucket).or_insert_with(Vec::new);
+        if let Some(player) = waiting.iter_mut().find(|x| x.address == address) {
+            player.last_request = now;
+        } else {
+            waiting.push(WaitingPlayer {
+                address,
+                last_request: now,
+            });
+        }
+
+        if waiting.len() < num_players {
+            return vec![];
+        }
+
+        let players: Vec<SocketAddr> = waiting.drain(..num_players).map(|x| x.address).collect();
+        for player in &players {
+            self.matched.insert(
+                *player,
+                Match {
+                    players: players.clone(),
+                    created: now,
+                },
+            );
+        }
+        players
+            .iter()
+            .map(|player| (*player, response(*player, &players)))
+            .collect()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        for waiting in self.waiting.values_mut() {
+            waiting.retain(|x| now.duration_since(x.last_request) < REQUEST_EXPIRY);
+        }
+        self.waiting.retain(|_, waiting| !waiting.is_empty());
+        self.matched
+            .retain(|_, found| now.duration_since(found.created) < MATCH_EXPIRY);
+    }
+}
+
+/// Each player is sent the addresses of every other player in the match
+fn response(address: SocketAddr, players: &[SocketAddr]) -> MatchMakingResponse {
+    MatchMakingResponse {
+        addresses: players.iter().filter(|x| **x != address).cloned().collect(),
+    }
+}
+
+#[cfg(test)]
+fn test_request(region: &str, build_version: &str, num_players: u8) -> MatchMakingRequest {
+    MatchMakingRequest {
+        region: region.into(),
+        build_version: build_version.into(),
+        num_players,
+    }
+}
+
+#[test]
+fn match_players_in_same_bucket() {
+    let now = Instant::now();
+    let mut server = MatchMakingServer::new();
+    let a: SocketAddr = "10.0.0.1:8413".parse().unwrap();
+    let b: SocketAddr = "10.0.0.2:8413".parse().unwrap();
+    let c: SocketAddr = "10.0.0.3:8413".parse().unwrap();
+    let d: SocketAddr = "10.0.0.4:8413".parse().unwrap();
+
+    assert!(server
+        .request(a, test_request("AU", "v1", 3), now)
+        .is_empty());
+    // different region, build_version or num_players are never matched together
+    assert!(server
+        .request(b, test_request("US", "v1", 3), now)
+        .is_empty());
+    assert!(server
+        .request(c, test_request("AU", "v2", 3), now)
+        .is_empty());
+    assert!(server
+        .request(d, test_request("AU", "v1", 2), now)
+        .is_empty());
+
+    assert!(server
+        .request(b, test_request("AU", "v1", 3), now)
+        .is_empty());
+    let responses = server.request(c, test_request("AU", "v1", 3), now);
+    assert_eq!(responses.len(), 3);
+    for (address, response) in responses {
+        assert_eq!(response.addresses.len(), 2);
+        assert!(!response.addresses.contains(&address));
+    }
+
+    // a resent request receives the same match
+    let responses = server.request(a, test_request("AU", "v1", 3), now);
+    assert_eq!(responses.len(), 1);
+    assert_eq!(responses[0].1.addresses, vec![b, c]);
+}
+
+#[test]
+fn expire_stale_requests() {
+    let now = Instant::now();
+    let mut server = MatchMakingServer::new();
+    let a: SocketAddr = "10.0.0.1:8413".parse().unwrap();
+    let b: SocketAddr = "10.0.0.2:8413".parse().unwrap();
+
+    assert!(server
+        .request(a, test_request("AU", "v1", 2), now)
+        .is_empty());
+    server.expire(now + REQUEST_EXPIRY);
+    assert!(server
+        .request(b, test_request("AU", "v1", 2), now + REQUEST_EXPIRY)
+        .is_empty());
+}